@@ -0,0 +1,124 @@
+use crate::interval::*;
+use crate::ray::*;
+use crate::vec3::*;
+
+//axis-aligned bounding box, stored as one interval per axis
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    pub x: Interval,
+    pub y: Interval,
+    pub z: Interval,
+}
+
+impl Default for Aabb {
+    fn default() -> Self {
+        Self {
+            x: EMPTY,
+            y: EMPTY,
+            z: EMPTY,
+        }
+    }
+}
+
+impl Aabb {
+    pub fn new(x: Interval, y: Interval, z: Interval) -> Self {
+        Self { x, y, z }
+    }
+
+    //a and b are treated as two opposite corners, in any order
+    pub fn new_from_points(a: &Point3, b: &Point3) -> Self {
+        Self {
+            x: Interval::new_with_init(a.x.min(b.x), a.x.max(b.x)),
+            y: Interval::new_with_init(a.y.min(b.y), a.y.max(b.y)),
+            z: Interval::new_with_init(a.z.min(b.z), a.z.max(b.z)),
+        }
+    }
+
+    pub fn new_from_boxes(box0: &Aabb, box1: &Aabb) -> Self {
+        Self {
+            x: Interval::new_from_intervals(&box0.x, &box1.x),
+            y: Interval::new_from_intervals(&box0.y, &box1.y),
+            z: Interval::new_from_intervals(&box0.z, &box1.z),
+        }
+    }
+
+    //avoid zero thickness on any axis so planar objects still get hit by the slab test
+    pub fn pad(&self) -> Self {
+        let delta = 0.0001;
+        let pad_axis = |ax: &Interval| {
+            if ax.size() < delta {
+                ax.expand(delta)
+            } else {
+                *ax
+            }
+        };
+        Self::new(pad_axis(&self.x), pad_axis(&self.y), pad_axis(&self.z))
+    }
+
+    pub fn axis(&self, n: usize) -> &Interval {
+        match n {
+            1 => &self.y,
+            2 => &self.z,
+            _ => &self.x,
+        }
+    }
+
+    pub fn longest_axis(&self) -> usize {
+        if self.x.size() > self.y.size() {
+            if self.x.size() > self.z.size() {
+                0
+            } else {
+                2
+            }
+        } else if self.y.size() > self.z.size() {
+            1
+        } else {
+            2
+        }
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        Point3::new(
+            0.5 * (self.x.min + self.x.max),
+            0.5 * (self.y.min + self.y.max),
+            0.5 * (self.z.min + self.z.max),
+        )
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let dx = self.x.size();
+        let dy = self.y.size();
+        let dz = self.z.size();
+        2. * (dx * dy + dy * dz + dz * dx)
+    }
+
+    //slab test, the interval is narrowed axis by axis until it is empty
+    pub fn hit(&self, r: &Ray, ray_t: Interval) -> bool {
+        let mut ray_t = ray_t;
+        let origin = [r.origin.x, r.origin.y, r.origin.z];
+        let direction = [r.direction.x, r.direction.y, r.direction.z];
+
+        for a in 0..3 {
+            let ax = self.axis(a);
+            let inv_d = 1. / direction[a];
+
+            let mut t0 = (ax.min - origin[a]) * inv_d;
+            let mut t1 = (ax.max - origin[a]) * inv_d;
+            if inv_d < 0. {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+
+            if t0 > ray_t.min {
+                ray_t.min = t0;
+            }
+            if t1 < ray_t.max {
+                ray_t.max = t1;
+            }
+
+            if ray_t.max <= ray_t.min {
+                return false;
+            }
+        }
+        true
+    }
+}
@@ -0,0 +1,404 @@
+use crate::aabb::*;
+use crate::hittable::*;
+use crate::hittable_list::*;
+use crate::interval::*;
+use crate::ray::*;
+use crate::vec3::*;
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+//bounding volume hierarchy node, each node splits its objects in two along the longest axis
+pub struct BvhNode {
+    left: Arc<dyn Hittable>,
+    right: Arc<dyn Hittable>,
+    bbox: Aabb,
+}
+
+impl BvhNode {
+    pub fn new(list: &HittableList) -> Self {
+        let mut objects: Vec<Arc<dyn Hittable>> = list.objects.iter().cloned().collect();
+        Self::new_from_objects(&mut objects)
+    }
+
+    pub fn new_from_objects(objects: &mut [Arc<dyn Hittable>]) -> Self {
+        let bbox = objects.iter().fold(Aabb::default(), |bbox, object| {
+            Aabb::new_from_boxes(&bbox, &object.bounding_box())
+        });
+
+        let axis = bbox.longest_axis();
+
+        let (left, right): (Arc<dyn Hittable>, Arc<dyn Hittable>) = match objects.len() {
+            0 => {
+                let empty: Arc<dyn Hittable> = Arc::new(HittableList::new());
+                (empty.clone(), empty)
+            }
+            1 => (objects[0].clone(), objects[0].clone()),
+            2 => (objects[0].clone(), objects[1].clone()),
+            len => {
+                objects.sort_by(|a, b| box_compare(a, b, axis));
+
+                let (lower, upper) = objects.split_at_mut(len / 2);
+                (
+                    Arc::new(Self::new_from_objects(lower)),
+                    Arc::new(Self::new_from_objects(upper)),
+                )
+            }
+        };
+
+        Self { left, right, bbox }
+    }
+}
+
+fn box_compare(a: &Arc<dyn Hittable>, b: &Arc<dyn Hittable>, axis: usize) -> Ordering {
+    let a_min = a.bounding_box().axis(axis).min;
+    let b_min = b.bounding_box().axis(axis).min;
+    a_min.total_cmp(&b_min)
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        if !self.bbox.hit(r, ray_t) {
+            return false;
+        }
+
+        let hit_left = self.left.hit(r, ray_t, rec);
+        //only look for hits on the right that are closer than the left one
+        let right_max = if hit_left { rec.t } else { ray_t.max };
+        let hit_right = self
+            .right
+            .hit(r, Interval::new_with_init(ray_t.min, right_max), rec);
+
+        hit_left || hit_right
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+const SAH_BUCKETS: usize = 12;
+const MAX_PRIMS_IN_LEAF: usize = 4;
+//keeps the traversal stack in FlatBvh::hit bounded
+const MAX_BVH_DEPTH: usize = 64;
+//cost of visiting a node relative to intersecting one primitive
+const TRAVERSAL_COST: f64 = 0.125;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SplitMethod {
+    //split at the midpoint of the centroid bounds
+    Middle,
+    //binned surface area heuristic
+    Sah,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct BvhStats {
+    pub primitive_count: usize,
+    pub node_count: usize,
+    pub leaf_count: usize,
+    pub max_depth: usize,
+    pub min_leaf_size: usize,
+    pub max_leaf_size: usize,
+    pub avg_leaf_size: f64,
+    //expected cost of a ray query, relative to one primitive test
+    pub sah_cost: f64,
+}
+
+#[derive(Clone, Copy)]
+struct LinearBvhNode {
+    bbox: Aabb,
+    //first primitive for leaves, second child for interior nodes (the first child follows directly)
+    offset: usize,
+    prim_count: usize,
+    axis: usize,
+}
+
+struct BuildPrimitive {
+    index: usize,
+    bbox: Aabb,
+    centroid: Point3,
+}
+
+//BVH flattened into a depth-first node array, traversed without recursion
+pub struct FlatBvh {
+    nodes: Vec<LinearBvhNode>,
+    primitives: Vec<Arc<dyn Hittable>>,
+    stats: BvhStats,
+}
+
+impl FlatBvh {
+    pub fn new(list: &HittableList, method: SplitMethod) -> Self {
+        let objects: Vec<Arc<dyn Hittable>> = list.objects.iter().cloned().collect();
+
+        let mut build_prims: Vec<BuildPrimitive> = objects
+            .iter()
+            .enumerate()
+            .map(|(index, object)| {
+                let bbox = object.bounding_box();
+                BuildPrimitive {
+                    index,
+                    bbox,
+                    centroid: bbox.centroid(),
+                }
+            })
+            .collect();
+
+        let mut bvh = Self {
+            nodes: Vec::with_capacity(2 * objects.len()),
+            primitives: Vec::with_capacity(objects.len()),
+            stats: BvhStats {
+                primitive_count: objects.len(),
+                min_leaf_size: usize::MAX,
+                ..Default::default()
+            },
+        };
+
+        if !build_prims.is_empty() {
+            bvh.build(&objects, &mut build_prims, method, 1);
+            bvh.stats.avg_leaf_size = objects.len() as f64 / bvh.stats.leaf_count as f64;
+            bvh.stats.sah_cost = bvh.node_cost(0);
+        } else {
+            bvh.stats.min_leaf_size = 0;
+        }
+
+        bvh
+    }
+
+    pub fn stats(&self) -> &BvhStats {
+        &self.stats
+    }
+
+    fn build(
+        &mut self,
+        objects: &[Arc<dyn Hittable>],
+        prims: &mut [BuildPrimitive],
+        method: SplitMethod,
+        depth: usize,
+    ) {
+        let bbox = prims.iter().fold(Aabb::default(), |bbox, prim| {
+            Aabb::new_from_boxes(&bbox, &prim.bbox)
+        });
+        let centroid_bounds = prims.iter().fold(Aabb::default(), |bbox, prim| {
+            Aabb::new_from_boxes(
+                &bbox,
+                &Aabb::new_from_points(&prim.centroid, &prim.centroid),
+            )
+        });
+        let axis = centroid_bounds.longest_axis();
+
+        let node_index = self.nodes.len();
+        self.nodes.push(LinearBvhNode {
+            bbox,
+            offset: 0,
+            prim_count: 0,
+            axis,
+        });
+        self.stats.node_count += 1;
+        self.stats.max_depth = self.stats.max_depth.max(depth);
+
+        let mid = if prims.len() == 1
+            || depth >= MAX_BVH_DEPTH
+            || centroid_bounds.axis(axis).size() <= 0.
+        {
+            None
+        } else {
+            match method {
+                SplitMethod::Middle => Some(split_middle(prims, &centroid_bounds, axis)),
+                SplitMethod::Sah => split_sah(prims, &bbox, &centroid_bounds, axis),
+            }
+        };
+
+        match mid {
+            Some(mid) => {
+                let (lower, upper) = prims.split_at_mut(mid);
+                self.build(objects, lower, method, depth + 1);
+                self.nodes[node_index].offset = self.nodes.len();
+                self.build(objects, upper, method, depth + 1);
+            }
+            None => {
+                let node = &mut self.nodes[node_index];
+                node.offset = self.primitives.len();
+                node.prim_count = prims.len();
+                self.primitives
+                    .extend(prims.iter().map(|prim| objects[prim.index].clone()));
+
+                self.stats.leaf_count += 1;
+                self.stats.min_leaf_size = self.stats.min_leaf_size.min(prims.len());
+                self.stats.max_leaf_size = self.stats.max_leaf_size.max(prims.len());
+            }
+        }
+    }
+
+    fn node_cost(&self, index: usize) -> f64 {
+        let node = &self.nodes[index];
+        if node.prim_count > 0 {
+            return node.prim_count as f64;
+        }
+
+        let area = node.bbox.surface_area();
+        let child_cost = |child: usize| {
+            let child_area = self.nodes[child].bbox.surface_area();
+            child_area / area * self.node_cost(child)
+        };
+        TRAVERSAL_COST + child_cost(index + 1) + child_cost(node.offset)
+    }
+}
+
+fn split_middle(prims: &mut [BuildPrimitive], centroid_bounds: &Aabb, axis: usize) -> usize {
+    let ax = centroid_bounds.axis(axis);
+    let midpoint = 0.5 * (ax.min + ax.max);
+
+    let mut mid = 0;
+    for i in 0..prims.len() {
+        if centroid_axis(&prims[i], axis) < midpoint {
+            prims.swap(i, mid);
+            mid += 1;
+        }
+    }
+
+    if mid == 0 || mid == prims.len() {
+        //all centroids on one side, fall back to equal counts
+        mid = prims.len() / 2;
+        prims.select_nth_unstable_by(mid, |a, b| {
+            centroid_axis(a, axis).total_cmp(&centroid_axis(b, axis))
+        });
+    }
+    mid
+}
+
+//returns None when a leaf is cheaper than the best split
+fn split_sah(
+    prims: &mut [BuildPrimitive],
+    bbox: &Aabb,
+    centroid_bounds: &Aabb,
+    axis: usize,
+) -> Option<usize> {
+    if prims.len() <= 2 {
+        let mid = prims.len() / 2;
+        prims.select_nth_unstable_by(mid, |a, b| {
+            centroid_axis(a, axis).total_cmp(&centroid_axis(b, axis))
+        });
+        return Some(mid);
+    }
+
+    let ax = centroid_bounds.axis(axis);
+    let bucket_of = |prim: &BuildPrimitive| {
+        let offset = (centroid_axis(prim, axis) - ax.min) / ax.size();
+        ((offset * SAH_BUCKETS as f64) as usize).min(SAH_BUCKETS - 1)
+    };
+
+    let mut counts = [0usize; SAH_BUCKETS];
+    let mut bounds = [Aabb::default(); SAH_BUCKETS];
+    for prim in prims.iter() {
+        let b = bucket_of(prim);
+        counts[b] += 1;
+        bounds[b] = Aabb::new_from_boxes(&bounds[b], &prim.bbox);
+    }
+
+    //cost of splitting after each bucket, sweeping from both ends
+    let mut costs = [0.; SAH_BUCKETS - 1];
+
+    let mut count_below = 0;
+    let mut bound_below = Aabb::default();
+    for i in 0..SAH_BUCKETS - 1 {
+        count_below += counts[i];
+        bound_below = Aabb::new_from_boxes(&bound_below, &bounds[i]);
+        if count_below > 0 {
+            costs[i] += count_below as f64 * bound_below.surface_area();
+        }
+    }
+
+    let mut count_above = 0;
+    let mut bound_above = Aabb::default();
+    for i in (1..SAH_BUCKETS).rev() {
+        count_above += counts[i];
+        bound_above = Aabb::new_from_boxes(&bound_above, &bounds[i]);
+        if count_above > 0 {
+            costs[i - 1] += count_above as f64 * bound_above.surface_area();
+        }
+    }
+
+    let (min_bucket, min_cost) = costs
+        .iter()
+        .enumerate()
+        .min_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(i, cost)| (i, TRAVERSAL_COST + cost / bbox.surface_area()))
+        .unwrap();
+
+    let leaf_cost = prims.len() as f64;
+    if prims.len() <= MAX_PRIMS_IN_LEAF && min_cost >= leaf_cost {
+        return None;
+    }
+
+    let mut mid = 0;
+    for i in 0..prims.len() {
+        if bucket_of(&prims[i]) <= min_bucket {
+            prims.swap(i, mid);
+            mid += 1;
+        }
+    }
+    Some(mid)
+}
+
+fn centroid_axis(prim: &BuildPrimitive, axis: usize) -> f64 {
+    match axis {
+        1 => prim.centroid.y,
+        2 => prim.centroid.z,
+        _ => prim.centroid.x,
+    }
+}
+
+impl Hittable for FlatBvh {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        if self.nodes.is_empty() {
+            return false;
+        }
+
+        let dir_is_neg = [r.direction.x < 0., r.direction.y < 0., r.direction.z < 0.];
+        let mut stack = [0usize; MAX_BVH_DEPTH];
+        let mut stack_len = 0;
+        let mut current = 0;
+
+        let mut hit_anything = false;
+        let mut closest_so_far = ray_t.max;
+
+        loop {
+            let node = &self.nodes[current];
+            let node_t = Interval::new_with_init(ray_t.min, closest_so_far);
+
+            if node.bbox.hit(r, node_t) {
+                if node.prim_count > 0 {
+                    for object in &self.primitives[node.offset..node.offset + node.prim_count] {
+                        if object.hit(r, Interval::new_with_init(ray_t.min, closest_so_far), rec) {
+                            hit_anything = true;
+                            closest_so_far = rec.t;
+                        }
+                    }
+                } else {
+                    //visit the nearer child first, the other one waits on the stack
+                    let (near, far) = if dir_is_neg[node.axis] {
+                        (node.offset, current + 1)
+                    } else {
+                        (current + 1, node.offset)
+                    };
+                    stack[stack_len] = far;
+                    stack_len += 1;
+                    current = near;
+                    continue;
+                }
+            }
+
+            if stack_len == 0 {
+                break;
+            }
+            stack_len -= 1;
+            current = stack[stack_len];
+        }
+
+        hit_anything
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.nodes.first().map_or(Aabb::default(), |node| node.bbox)
+    }
+}
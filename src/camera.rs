@@ -1,39 +1,139 @@
+use crate::hdr::*;
 use crate::hittable::*;
 use crate::hittable_list::*;
 use crate::image::*;
 use crate::interval::*;
+use crate::pdf::*;
 use crate::ray::*;
 use crate::sphere::*;
+use crate::tonemap::*;
+use crate::transform::*;
 use crate::utility::*;
 use crate::vec3::*;
 use crate::Lambertian;
+#[cfg(feature = "viewer")]
+use druid::{Data, Lens};
 use rand::thread_rng;
 use rand::Rng;
 use rayon::prelude::*;
-use std::ops::Deref;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::ops::{Add, Deref};
 use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::sync::Mutex;
-use druid::Data;
 
-#[derive(Debug, Clone, Data)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "viewer", derive(Data, Lens))]
+#[serde(default)]
 pub struct Camera {
     pub aspect_ratio: f64,
     pub image_width: usize,
     pub samples_per_pixel: usize,
-    pub max_depth: usize,
+    pub max_depth: usize, //hard cap on bounces, paths normally end earlier by russian roulette
+    pub roulette_depth: usize, //bounces every path takes before it may be terminated
     pub vfov: f64,
     pub lookfrom: Point3,
     pub lookat: Point3,
     pub vup: Vec3,
+    pub defocus_angle: f64, //variation angle of rays through each pixel
+    pub focus_dist: f64,    //distance from lookfrom to the plane of perfect focus
+    //moving objects are only bounded between times 0 and 1, see check_shutter
+    pub shutter_open: f64,
+    pub shutter_close: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub background: Option<Color>, //color of rays that escape the scene, None for the sky gradient
+    pub tone_map: ToneMap,
+    #[serde(skip)]
     image_height: usize,
+    #[serde(skip)]
     center: Point3,
+    #[serde(skip)]
     pixel00_loc: Point3,
+    #[serde(skip)]
     pixel_delta_lr: Vec3,
+    #[serde(skip)]
     pixel_delta_ud: Vec3,
+    #[serde(skip)]
     u: Vec3,
+    #[serde(skip)]
     v: Vec3,
+    #[serde(skip)]
     w: Vec3,
+    #[serde(skip)]
+    defocus_disk_u: Vec3,
+    #[serde(skip)]
+    defocus_disk_v: Vec3,
+}
+
+//how the paths of a render ended
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PathStats {
+    pub paths: usize,
+    pub bounces: usize, //hits that scattered or absorbed the path, over all paths
+    pub roulette_terminated: usize,
+    pub depth_capped: usize, //paths cut off by max_depth
+}
+
+impl PathStats {
+    //average number of hits per camera ray
+    pub fn average_length(&self) -> f64 {
+        if self.paths == 0 {
+            return 0.;
+        }
+        self.bounces as f64 / self.paths as f64
+    }
+}
+
+impl Add for PathStats {
+    type Output = PathStats;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        PathStats {
+            paths: self.paths + rhs.paths,
+            bounces: self.bounces + rhs.bounces,
+            roulette_terminated: self.roulette_terminated + rhs.roulette_terminated,
+            depth_capped: self.depth_capped + rhs.depth_capped,
+        }
+    }
+}
+
+//scattering hits ray_color keeps on the stack, longer paths spill onto the heap
+const STACK_VERTICES: usize = 32;
+
+//what one bounce hands to the next
+struct PathState {
+    depth: usize, //bounces left before max_depth
+    bounce: usize,
+    //product of the attenuations so far, divided by the survival probabilities
+    throughput: Color,
+    //scales the light found at the next hit, it is below 1 when the previous bounce could
+    //have picked the ray by sampling the lights as well
+    emission_weight: f64,
+}
+
+impl PathState {
+    fn new(depth: usize) -> Self {
+        Self {
+            depth,
+            bounce: 0,
+            throughput: Color::new(1., 1., 1.),
+            emission_weight: 1.,
+        }
+    }
+}
+
+//what following a path for one segment found
+enum Bounce {
+    //all the light the rest of the path sees
+    End(Color),
+    //light added at the hit, plus whatever scattered finds, attenuated
+    Scatter {
+        light: Color,
+        attenuation: Color,
+        scattered: Ray,
+    },
 }
 
 impl Default for Camera {
@@ -43,10 +143,17 @@ impl Default for Camera {
             image_width: 100,
             samples_per_pixel: 10,
             max_depth: 10,
+            roulette_depth: 3,
             vfov: 90.,
             lookfrom: Point3::new(0., 0., -1.),
             lookat: Point3::new(0., 0., 0.),
             vup: Vec3::new(0., 1., 0.),
+            defocus_angle: 0.,
+            focus_dist: 10.,
+            shutter_open: 0.,
+            shutter_close: 1.,
+            background: None,
+            tone_map: ToneMap::default(),
             image_height: Default::default(),
             center: Default::default(),
             pixel00_loc: Default::default(),
@@ -55,6 +162,8 @@ impl Default for Camera {
             u: Default::default(),
             v: Default::default(),
             w: Default::default(),
+            defocus_disk_u: Default::default(),
+            defocus_disk_v: Default::default(),
         }
     }
 }
@@ -65,11 +174,9 @@ impl Camera {
         self.image_height = (self.image_width as f64 / self.aspect_ratio) as usize;
         self.center = self.lookfrom.clone();
 
-        let focal_length = (&self.lookfrom - &self.lookat).length();
-
         let theta = deg_to_rad(self.vfov);
         let h = (theta / 2.).tan();
-        let viewport_height = 2. * h * focal_length;
+        let viewport_height = 2. * h * self.focus_dist;
 
         let viewport_width = viewport_height * self.image_width as f64 / self.image_height as f64; //self.aspect_ratio;
 
@@ -84,34 +191,225 @@ impl Camera {
         self.pixel_delta_ud = &viewport_ud / self.image_height as f64;
 
         let viewport_upper_left =
-            &self.center - (focal_length * &self.w) - viewport_lr / 2. - viewport_ud / 2.; //Vec3::new(0., 0., focal_length) - &viewport_lr / 2. - &viewport_ud / 2.;
+            &self.center - (self.focus_dist * &self.w) - viewport_lr / 2. - viewport_ud / 2.; //Vec3::new(0., 0., focal_length) - &viewport_lr / 2. - &viewport_ud / 2.;
 
         self.pixel00_loc =
             viewport_upper_left + 0.5 * (&self.pixel_delta_lr + &self.pixel_delta_ud);
+
+        let defocus_radius = self.focus_dist * deg_to_rad(self.defocus_angle / 2.).tan();
+        self.defocus_disk_u = defocus_radius * &self.u;
+        self.defocus_disk_v = defocus_radius * &self.v;
     }
 
-    pub fn ray_color(r: &Ray, depth: usize, world: &dyn Hittable) -> Color {
-        let rec: &mut HitRecord = &mut HitRecord {
-            p: Vec3::default(),
-            normal: Vec3::default(),
-            mat: Arc::new(Lambertian::new(Color::default())),
-            t: 0.,
-            front_face: false,
+    //lights are sampled directly at every bounce off a non-specular material, with an empty
+    //list the path only finds emitters by chance
+    pub fn ray_color(
+        &self,
+        r: &Ray,
+        depth: usize,
+        world: &dyn Hittable,
+        lights: &HittableList,
+    ) -> Color {
+        self.ray_color_with_stats(r, depth, world, lights, &mut PathStats::default())
+    }
+
+    //adds how the path ended to stats
+    pub fn ray_color_with_stats(
+        &self,
+        r: &Ray,
+        depth: usize,
+        world: &dyn Hittable,
+        lights: &HittableList,
+        stats: &mut PathStats,
+    ) -> Color {
+        stats.paths += 1;
+        let mut path = PathState::new(depth);
+        let mut rec = HitRecord::new_blank();
+        //light and attenuation of every scattering hit, summed back to front at the end so
+        //the additions round exactly like the recursive evaluation. Paths longer than the
+        //stack buffer are rare (only materials that don't absorb survive roulette that long)
+        //and spill into a Vec, which only allocates then
+        let mut vertices: [(Color, Color); STACK_VERTICES] =
+            std::array::from_fn(|_| Default::default());
+        let mut len = 0;
+        let mut spilled = Vec::new();
+        let mut r = r.clone();
+
+        let color = loop {
+            match self.bounce(&r, world, lights, &mut path, &mut rec, stats) {
+                Bounce::End(color) => break color,
+                Bounce::Scatter {
+                    light,
+                    attenuation,
+                    scattered,
+                } => {
+                    if len < STACK_VERTICES {
+                        vertices[len] = (light, attenuation);
+                        len += 1;
+                    } else {
+                        spilled.push((light, attenuation));
+                    }
+                    r = scattered;
+                }
+            }
         };
 
-        if depth <= 0 {
-            return Color::new(0., 0., 0.);
+        let add_vertex = |color, (light, attenuation): (Color, Color)| light + attenuation * color;
+        let color = spilled.into_iter().rev().fold(color, add_vertex);
+        vertices[..len]
+            .iter_mut()
+            .rev()
+            .map(std::mem::take)
+            .fold(color, add_vertex)
+    }
+
+    //the same bounces evaluated by recursion, the reference the loop is tested and timed
+    //against
+    #[cfg(test)]
+    pub fn ray_color_recursive(
+        &self,
+        r: &Ray,
+        depth: usize,
+        world: &dyn Hittable,
+        lights: &HittableList,
+        stats: &mut PathStats,
+    ) -> Color {
+        stats.paths += 1;
+        self.trace(r, world, lights, &mut PathState::new(depth), stats)
+    }
+
+    #[cfg(test)]
+    fn trace(
+        &self,
+        r: &Ray,
+        world: &dyn Hittable,
+        lights: &HittableList,
+        path: &mut PathState,
+        stats: &mut PathStats,
+    ) -> Color {
+        let mut rec = HitRecord::new_blank();
+        match self.bounce(r, world, lights, path, &mut rec, stats) {
+            Bounce::End(color) => color,
+            Bounce::Scatter {
+                light,
+                attenuation,
+                scattered,
+            } => light + attenuation * self.trace(&scattered, world, lights, path, stats),
         }
+    }
+
+    //follows r to its next hit and moves path on to the scattered ray
+    fn bounce(
+        &self,
+        r: &Ray,
+        world: &dyn Hittable,
+        lights: &HittableList,
+        path: &mut PathState,
+        rec: &mut HitRecord,
+        stats: &mut PathStats,
+    ) -> Bounce {
+        if path.depth == 0 {
+            stats.depth_capped += 1;
+            return Bounce::End(Color::new(0., 0., 0.));
+        }
+
+        if !world.hit(r, Interval::new_with_init(0.001, INFINITY), rec) {
+            return Bounce::End(self.background_color(r));
+        }
+        stats.bounces += 1;
+
+        let mut scattered = Ray::default();
+        let mut attenuation = Color::default();
+        let color_from_emission = path.emission_weight * rec.mat.emitted(rec.u, rec.v, &rec.p);
 
-        if world.hit(r, Interval::new_with_init(0.001, INFINITY), rec) {
-            let mut scattered = Ray::default();
-            let mut attenuation = Color::default();
+        let scatters = rec.mat.scatter(r, rec, &mut attenuation, &mut scattered);
 
-            if rec.mat.scatter(r, rec, &mut attenuation, &mut scattered) {
-                return attenuation * Self::ray_color(&scattered, depth - 1, world);
-            } else {
-                return Color::default();
+        //multiple importance sampling, both the light sample and the scattered ray count
+        //towards the direct light, weighted by how likely each was to pick its direction.
+        //the last bounce can't reach a light through the scattered ray either
+        let light_pdf = HittablePdf::new(lights, &rec.p);
+        let samples_lights = !rec.mat.is_specular() && !lights.objects.is_empty() && path.depth > 1;
+        //the light sample doesn't depend on the scattered ray, and still counts when that
+        //ray is absorbed
+        let color_from_lights = if samples_lights {
+            Self::sample_lights(r, rec, world, &light_pdf)
+        } else {
+            Color::new(0., 0., 0.)
+        };
+        if !scatters {
+            return Bounce::End(color_from_emission + color_from_lights);
+        }
+
+        //russian roulette, paths carrying little light are likely to end and the survivors
+        //make up for them by carrying proportionally more, which keeps the estimate unbiased
+        let mut throughput = path.throughput.clone() * attenuation.clone();
+        if path.bounce >= self.roulette_depth {
+            let survival = throughput.x.max(throughput.y).max(throughput.z).min(1.);
+            if random_f64() >= survival {
+                stats.roulette_terminated += 1;
+                return Bounce::End(color_from_emission + color_from_lights);
             }
+            attenuation = attenuation / survival;
+            throughput = throughput / survival;
+        }
+
+        let scatter_weight = if samples_lights {
+            power_heuristic(
+                rec.mat.scattering_pdf(r, rec, &scattered.direction),
+                light_pdf.value(&scattered.direction),
+            )
+        } else {
+            1.
+        };
+        *path = PathState {
+            depth: path.depth - 1,
+            bounce: path.bounce + 1,
+            throughput,
+            emission_weight: scatter_weight,
+        };
+
+        Bounce::Scatter {
+            light: color_from_emission + color_from_lights,
+            attenuation,
+            scattered,
+        }
+    }
+
+    //shadow ray towards a random point on the lights, whatever it reaches first is the light
+    //arriving from that direction
+    fn sample_lights(
+        r_in: &Ray,
+        rec: &HitRecord,
+        world: &dyn Hittable,
+        light_pdf: &dyn Pdf,
+    ) -> Color {
+        let direction = light_pdf.generate();
+        let pdf = light_pdf.value(&direction);
+        if pdf <= 0. {
+            return Color::new(0., 0., 0.);
+        }
+
+        let bsdf = rec.mat.bsdf(r_in, rec, &direction);
+        let weight = power_heuristic(pdf, rec.mat.scattering_pdf(r_in, rec, &direction));
+        let shadow = Ray::new_with_time(rec.p.clone(), direction, r_in.time);
+        let mut light_rec = rec.clone();
+        if !world.hit(
+            &shadow,
+            Interval::new_with_init(0.001, INFINITY),
+            &mut light_rec,
+        ) {
+            return Color::new(0., 0., 0.);
+        }
+
+        let emitted = light_rec
+            .mat
+            .emitted(light_rec.u, light_rec.v, &light_rec.p);
+        weight * bsdf * emitted / pdf
+    }
+
+    fn background_color(&self, r: &Ray) -> Color {
+        if let Some(background) = &self.background {
+            return background.clone();
         }
 
         let unit_direction = r.direction.unit();
@@ -121,69 +419,262 @@ impl Camera {
         (1. - a) * Color::new(1., 1., 1.) + a * Color::new(0.5, 0.7, 1.)
     }
 
-    pub fn render(&mut self, world: &dyn Hittable) -> Image {
+    pub fn render(&mut self, world: &dyn Hittable, lights: &HittableList) -> Image {
+        let tone_map = self.tone_map;
+        tone_map.apply(&self.render_hdr(world, lights))
+    }
+
+    //average radiance per pixel without clamping or gamma
+    pub fn render_hdr(&mut self, world: &dyn Hittable, lights: &HittableList) -> HdrImage {
         Self::initialize(self);
-        let mut image = Image::new(self.image_height, self.image_width);
+        let mut image = HdrImage::new(self.image_height, self.image_width);
 
         for row in 0..self.image_height {
             for col in 0..self.image_width {
                 let mut pixel_color = Color::new(0., 0., 0.);
                 for sample in 0..self.samples_per_pixel {
                     let r = self.get_ray(col, row);
-                    pixel_color = pixel_color + Self::ray_color(&r, self.max_depth, world);
+                    pixel_color = pixel_color + self.ray_color(&r, self.max_depth, world, lights);
                 }
 
-                image.pixels[row][col] = pixel_color.to_pixel_with_spp(self.samples_per_pixel);
+                image.set(row, col, &(pixel_color / self.samples_per_pixel as f64));
             }
         }
         image
     }
 
-    pub fn parallel_render(&mut self, world: &dyn Hittable) -> Image {
+    pub fn parallel_render(&mut self, world: &dyn Hittable, lights: &HittableList) -> Image {
+        self.parallel_render_with_progress(world, lights, |_, _| {})
+    }
+
+    //progress is called with (finished rows, total rows) each time a row completes
+    pub fn parallel_render_with_progress(
+        &mut self,
+        world: &dyn Hittable,
+        lights: &HittableList,
+        progress: impl Fn(usize, usize) + Sync,
+    ) -> Image {
+        let (image, _) = self.parallel_render_hdr_with_progress(world, lights, progress);
+        self.tone_map.apply(&image)
+    }
+
+    pub fn parallel_render_hdr(&mut self, world: &dyn Hittable, lights: &HittableList) -> HdrImage {
+        self.parallel_render_hdr_with_progress(world, lights, |_, _| {})
+            .0
+    }
+
+    //also returns how the paths of the render ended
+    pub fn parallel_render_hdr_with_progress(
+        &mut self,
+        world: &dyn Hittable,
+        lights: &HittableList,
+        progress: impl Fn(usize, usize) + Sync,
+    ) -> (HdrImage, PathStats) {
+        Self::initialize(self);
+        let samples = self.samples_per_pixel;
+        self.parallel_render_samples(world, lights, samples, progress, || false)
+            .expect("a render that can't be cancelled finishes")
+    }
+
+    //one progressive pass with its own sample count, averaged like a full render
+    pub fn parallel_render_pass(
+        &mut self,
+        world: &dyn Hittable,
+        lights: &HittableList,
+        samples: usize,
+    ) -> HdrImage {
+        self.parallel_render_pass_cancellable(world, lights, samples, || false)
+            .expect("a render that can't be cancelled finishes")
+    }
+
+    //a pass that gives up with None once cancelled returns true, which is checked before each
+    //row. cancelled must keep returning true once it has
+    pub fn parallel_render_pass_cancellable(
+        &mut self,
+        world: &dyn Hittable,
+        lights: &HittableList,
+        samples: usize,
+        cancelled: impl Fn() -> bool + Sync,
+    ) -> Option<HdrImage> {
         Self::initialize(self);
-        let image = Image::new(self.image_height, self.image_width);
-        let mut processed_image = Image::new(self.image_height, self.image_width);
-
-        processed_image
-            .pixels
-            .par_iter_mut()
-            .enumerate()
-            .for_each(|(x, row)| {
-                row.par_iter_mut().enumerate().for_each(|(y, pixel)| {
-                    // *pixel = Pixel { r: 0, g: 0, b: 0 };
-                    let pixel_color = (0..self.samples_per_pixel)
-                        .into_par_iter()
-                        .map(|_sample| {
-                            // set_device(0);
-                            let r = self.get_ray(y, x);
-
-                            Self::ray_color(&r, self.max_depth, world)
-                        })
-                        .sum::<Color>();
-                    *pixel = pixel_color.to_pixel_with_spp(self.samples_per_pixel)
-                });
-            });
-        processed_image
-    }
-
-    fn get_ray(&self, i: usize, j: usize) -> Ray {
+        self.parallel_render_samples(world, lights, samples, |_, _| {}, cancelled)
+            .map(|(image, _)| image)
+    }
+
+    //turns lookfrom around lookat, yaw about vup and pitch towards vup, never past the poles
+    pub fn orbit(&mut self, yaw_degrees: f64, pitch_degrees: f64) {
+        let up = self.vup.unit();
+        let offset = &self.lookfrom - &self.lookat;
+
+        let polar = offset.unit().dot(&up).clamp(-1., 1.).acos().to_degrees();
+        let pitch = polar - (polar - pitch_degrees).clamp(1., 179.);
+        let right = up.cross(&offset).unit();
+
+        let rotation = Mat4::rotation(&up, yaw_degrees) * Mat4::rotation(&right, -pitch);
+        self.lookfrom = &self.lookat + rotation.transform_vector(&offset);
+    }
+
+    //narrows (factor < 1) or widens the field of view
+    pub fn zoom(&mut self, factor: f64) {
+        self.vfov = (self.vfov * factor).clamp(1., 179.);
+    }
+
+    //moves lookfrom along the view direction, scaling its distance to lookat
+    pub fn dolly(&mut self, factor: f64) {
+        let offset = &self.lookfrom - &self.lookat;
+        let distance = offset.length();
+        let new_distance = (distance * factor).max(1e-3);
+        self.lookfrom = &self.lookat + (new_distance / distance) * offset;
+    }
+
+    //slides lookfrom and lookat together across the image plane
+    pub fn pan(&mut self, right: f64, up: f64) {
+        let w = (&self.lookfrom - &self.lookat).unit();
+        let u = self.vup.cross(&w).unit();
+        let v = w.cross(&u);
+        let offset = right * u + up * v;
+        self.lookfrom = &self.lookfrom + &offset;
+        self.lookat = &self.lookat + &offset;
+    }
+
+    //moves lookfrom and lookat together, forward along the view and up along vup
+    pub fn fly(&mut self, forward: f64, right: f64, up: f64) {
+        let w = (&self.lookfrom - &self.lookat).unit();
+        let u = self.vup.cross(&w).unit();
+        let offset = -forward * w + right * u + up * self.vup.unit();
+        self.lookfrom = &self.lookfrom + &offset;
+        self.lookat = &self.lookat + &offset;
+    }
+
+    //true when both cameras produce the same image, ignoring the sample count and tone mapping
+    pub fn same_view(&self, other: &Camera) -> bool {
+        self.aspect_ratio == other.aspect_ratio
+            && self.image_width == other.image_width
+            && self.max_depth == other.max_depth
+            && self.roulette_depth == other.roulette_depth
+            && self.vfov == other.vfov
+            && self.lookfrom == other.lookfrom
+            && self.lookat == other.lookat
+            && self.vup == other.vup
+            && self.defocus_angle == other.defocus_angle
+            && self.focus_dist == other.focus_dist
+            && self.shutter_open == other.shutter_open
+            && self.shutter_close == other.shutter_close
+            && self.background == other.background
+    }
+
+    fn parallel_render_samples(
+        &self,
+        world: &dyn Hittable,
+        lights: &HittableList,
+        samples: usize,
+        progress: impl Fn(usize, usize) + Sync,
+        cancelled: impl Fn() -> bool + Sync,
+    ) -> Option<(HdrImage, PathStats)> {
+        let rows_done = AtomicUsize::new(0);
+
+        let rows: Vec<(Vec<Color>, PathStats)> = (0..self.image_height)
+            .into_par_iter()
+            .map(|x| {
+                if cancelled() {
+                    return (Vec::new(), PathStats::default());
+                }
+                let row: Vec<(Color, PathStats)> = (0..self.image_width)
+                    .into_par_iter()
+                    .map(|y| {
+                        let (pixel_color, stats) = (0..samples)
+                            .into_par_iter()
+                            .map(|_sample| {
+                                // set_device(0);
+                                let r = self.get_ray(y, x);
+
+                                let mut stats = PathStats::default();
+                                let color = self.ray_color_with_stats(
+                                    &r,
+                                    self.max_depth,
+                                    world,
+                                    lights,
+                                    &mut stats,
+                                );
+                                (color, stats)
+                            })
+                            .reduce(
+                                || (Color::default(), PathStats::default()),
+                                |a, b| (a.0 + b.0, a.1 + b.1),
+                            );
+                        (pixel_color / samples as f64, stats)
+                    })
+                    .collect();
+                progress(
+                    rows_done.fetch_add(1, Ordering::Relaxed) + 1,
+                    self.image_height,
+                );
+                let stats = row
+                    .iter()
+                    .fold(PathStats::default(), |sum, (_, stats)| sum + *stats);
+                (row.into_iter().map(|(color, _)| color).collect(), stats)
+            })
+            .collect();
+        //rows skipped after cancelling are empty
+        if cancelled() {
+            return None;
+        }
+
+        let stats = rows
+            .iter()
+            .fold(PathStats::default(), |sum, (_, stats)| sum + *stats);
+        let image = HdrImage::new_with_init(self.image_height, self.image_width, |row, col| {
+            rows[row].0[col].clone()
+        });
+        Some((image, stats))
+    }
+
+    //rays outside times 0 to 1 would miss moving spheres in a BVH, whose boxes only cover
+    //that range
+    pub fn check_shutter(&self) -> io::Result<()> {
+        if 0. <= self.shutter_open
+            && self.shutter_open <= self.shutter_close
+            && self.shutter_close <= 1.
+        {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "shutter interval {} to {} is not within 0 to 1",
+                    self.shutter_open, self.shutter_close
+                ),
+            ))
+        }
+    }
+
+    pub fn get_ray(&self, i: usize, j: usize) -> Ray {
         let pixel_center = &self.pixel00_loc
             + (i as f64 * &self.pixel_delta_lr)
             + (j as f64 * &self.pixel_delta_ud);
 
         let pixel_sample = &pixel_center + self.pixel_sample_square();
-        let ray_origin = self.center.clone();
+        let ray_origin = if self.defocus_angle <= 0. {
+            self.center.clone()
+        } else {
+            self.defocus_disk_sample()
+        };
 
         let ray_direction = &pixel_sample - &ray_origin;
+        let ray_time = random_f64_in_range(self.shutter_open, self.shutter_close);
 
-        let ray = Ray::new(ray_origin, ray_direction);
-
-        ray
+        Ray::new_with_time(ray_origin, ray_direction, ray_time)
     }
 
     fn pixel_sample_square(&self) -> Vec3 {
         let px = -0.5 + random_f64();
         let py = -0.5 + random_f64();
-        return (px * &self.pixel_delta_lr) + (py * &self.pixel_delta_ud);
+        (px * &self.pixel_delta_lr) + (py * &self.pixel_delta_ud)
+    }
+
+    fn defocus_disk_sample(&self) -> Point3 {
+        //random point on the lens disk around the camera center
+        let p = Vec3::random_in_unit_disk();
+        &self.center + (p.x * &self.defocus_disk_u) + (p.y * &self.defocus_disk_v)
     }
 }
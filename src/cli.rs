@@ -0,0 +1,218 @@
+use crate::bvh::*;
+use crate::camera::*;
+use crate::hdr::*;
+use crate::image::*;
+use crate::scene::*;
+use crate::tonemap::*;
+use crate::utility::*;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+pub const USAGE: &str = "usage:
+    ray_tracer [scene]
+        open the scene (or the built-in one) in the viewer
+    ray_tracer render [scene] [--width N] [--spp N] [--depth N] [--output PATH]
+                      [--tonemap OPERATOR] [--exposure STOPS] [--white RADIANCE]
+        render without a window and write the image to PATH (default render.png)
+        .png and .ppm are 8-bit, .hdr, .pfm and .exr keep the unclamped radiance
+        OPERATOR is one of clamp, reinhard, extended_reinhard, aces, uncharted2";
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    View { scene: Option<PathBuf> },
+    Render(RenderOptions),
+}
+
+//camera overrides are only applied when given, otherwise the scene file decides
+#[derive(Debug, PartialEq)]
+pub struct RenderOptions {
+    pub scene: Option<PathBuf>,
+    pub width: Option<usize>,
+    pub samples_per_pixel: Option<usize>,
+    pub max_depth: Option<usize>,
+    pub tone_map: Option<ToneMapOperator>,
+    pub exposure: Option<f64>,
+    pub white_point: Option<f64>,
+    pub output: PathBuf,
+}
+
+impl Command {
+    pub fn scene(&self) -> Option<&Path> {
+        match self {
+            Command::View { scene } => scene.as_deref(),
+            Command::Render(options) => options.scene.as_deref(),
+        }
+    }
+}
+
+impl RenderOptions {
+    pub fn apply(&self, cam: &mut Camera) {
+        if let Some(width) = self.width {
+            cam.image_width = width;
+        }
+        if let Some(samples_per_pixel) = self.samples_per_pixel {
+            cam.samples_per_pixel = samples_per_pixel;
+        }
+        if let Some(max_depth) = self.max_depth {
+            cam.max_depth = max_depth;
+        }
+        if let Some(operator) = self.tone_map {
+            cam.tone_map.operator = operator;
+        }
+        if let Some(exposure) = self.exposure {
+            cam.tone_map.exposure = exposure;
+        }
+        if let Some(white_point) = self.white_point {
+            cam.tone_map.white_point = white_point;
+        }
+    }
+}
+
+//args without the program name
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> io::Result<Command> {
+    let mut args = args.into_iter().peekable();
+
+    if args.peek().map(String::as_str) != Some("render") {
+        let scene = args.next().map(PathBuf::from);
+        return match args.next() {
+            Some(extra) => Err(usage_error(&format!("unexpected argument: {}", extra))),
+            None => Ok(Command::View { scene }),
+        };
+    }
+    args.next();
+
+    let mut options = RenderOptions {
+        scene: None,
+        width: None,
+        samples_per_pixel: None,
+        max_depth: None,
+        tone_map: None,
+        exposure: None,
+        white_point: None,
+        output: PathBuf::from("render.png"),
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .ok_or_else(|| usage_error(&format!("missing value for {}", name)))
+        };
+        match arg.as_str() {
+            "--width" | "-w" => options.width = Some(parse_count(&arg, &value(&arg)?)?),
+            "--spp" | "-s" => options.samples_per_pixel = Some(parse_count(&arg, &value(&arg)?)?),
+            "--depth" | "-d" => options.max_depth = Some(parse_count(&arg, &value(&arg)?)?),
+            "--output" | "-o" => options.output = PathBuf::from(value(&arg)?),
+            "--tonemap" | "-t" => {
+                let name = value(&arg)?;
+                options.tone_map = Some(ToneMapOperator::from_name(&name).ok_or_else(|| {
+                    usage_error(&format!("unknown tone mapping operator: {}", name))
+                })?)
+            }
+            "--exposure" | "-e" => options.exposure = Some(parse_float(&arg, &value(&arg)?)?),
+            "--white" => options.white_point = Some(parse_float(&arg, &value(&arg)?)?),
+            flag if flag.starts_with('-') => {
+                return Err(usage_error(&format!("unknown option: {}", flag)))
+            }
+            _ if options.scene.is_none() => options.scene = Some(PathBuf::from(arg)),
+            _ => return Err(usage_error(&format!("unexpected argument: {}", arg))),
+        }
+    }
+
+    Ok(Command::Render(options))
+}
+
+//renders on all cores without opening a window, drawing a progress bar on stderr
+pub fn render_to_file(
+    options: &RenderOptions,
+    scene: &SceneDesc,
+    base_dir: &Path,
+) -> io::Result<()> {
+    //fail before rendering rather than after
+    if ImageFormat::from_path(&options.output).is_none()
+        && HdrFormat::from_path(&options.output).is_none()
+    {
+        return Err(unsupported_format("output", &options.output));
+    }
+
+    let mut cam = scene.camera.clone();
+    options.apply(&mut cam);
+    let mut warnings = Vec::new();
+    let (world, lights) = scene.build(base_dir, &mut warnings)?;
+    for warning in warnings {
+        eprintln!("warning: {}", warning);
+    }
+    let world = FlatBvh::new(&world, SplitMethod::Sah);
+
+    let time_start = Instant::now();
+    let (image, stats) = cam.parallel_render_hdr_with_progress(&world, &lights, |done, total| {
+        let mut stderr = io::stderr().lock();
+        let _ = write!(stderr, "\r{}", progress_bar(done, total, 40));
+        let _ = stderr.flush();
+    });
+    let time_elapsed = time_start.elapsed();
+    eprintln!();
+
+    cam.tone_map.save(&image, &options.output)?;
+
+    println!(
+        "Rendered {}x{} at {} spp to {} in {:.2?}",
+        image.width(),
+        image.height(),
+        cam.samples_per_pixel,
+        options.output.display(),
+        time_elapsed
+    );
+    println!(
+        "Average path length {:.2} bounces, {} of {} paths reached the depth limit",
+        stats.average_length(),
+        stats.depth_capped,
+        stats.paths
+    );
+    Ok(())
+}
+
+//e.g. "[##########----------]  50% (120/240 rows)"
+pub fn progress_bar(done: usize, total: usize, width: usize) -> String {
+    let fraction = if total == 0 {
+        1.
+    } else {
+        done.min(total) as f64 / total as f64
+    };
+    let filled = (fraction * width as f64) as usize;
+    format!(
+        "[{}{}] {:>3}% ({}/{} rows)",
+        "#".repeat(filled),
+        "-".repeat(width - filled),
+        (fraction * 100.) as usize,
+        done,
+        total
+    )
+}
+
+fn parse_count(name: &str, value: &str) -> io::Result<usize> {
+    match value.parse() {
+        Ok(count) if count > 0 => Ok(count),
+        _ => Err(usage_error(&format!(
+            "{} expects a positive integer, got {}",
+            name, value
+        ))),
+    }
+}
+
+fn parse_float(name: &str, value: &str) -> io::Result<f64> {
+    match value.parse::<f64>() {
+        Ok(number) if number.is_finite() => Ok(number),
+        _ => Err(usage_error(&format!(
+            "{} expects a number, got {}",
+            name, value
+        ))),
+    }
+}
+
+fn usage_error(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{}\n{}", message, USAGE),
+    )
+}
@@ -0,0 +1,84 @@
+use crate::aabb::*;
+use crate::hittable::*;
+use crate::interval::*;
+use crate::material::*;
+use crate::ray::*;
+use crate::texture::*;
+use crate::utility::*;
+use crate::vec3::*;
+use crate::Color;
+use std::sync::Arc;
+
+//volume of uniform density filling a closed boundary, rays scatter at an
+//exponentially distributed distance inside it
+pub struct ConstantMedium {
+    boundary: Arc<dyn Hittable>,
+    neg_inv_density: f64,
+    phase_function: Arc<dyn Material>,
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Arc<dyn Hittable>, density: f64, albedo: Color) -> Self {
+        Self::new_with_texture(boundary, density, Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn new_with_texture(
+        boundary: Arc<dyn Hittable>,
+        density: f64,
+        tex: Arc<dyn Texture>,
+    ) -> Self {
+        Self {
+            boundary,
+            neg_inv_density: -1. / density,
+            phase_function: Arc::new(Isotropic::new_with_texture(tex)),
+        }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let mut rec1 = rec.clone();
+        let mut rec2 = rec.clone();
+
+        //entry and exit along the whole line, so rays starting inside still find
+        //the boundary behind them
+        if !self.boundary.hit(r, UNIVERSE, &mut rec1) {
+            return false;
+        }
+        if !self.boundary.hit(
+            r,
+            Interval::new_with_init(rec1.t + 0.0001, INFINITY),
+            &mut rec2,
+        ) {
+            return false;
+        }
+
+        let t_enter = rec1.t.max(ray_t.min).max(0.);
+        let t_exit = rec2.t.min(ray_t.max);
+        if t_enter >= t_exit {
+            return false;
+        }
+
+        let ray_length = r.direction.length();
+        let distance_inside_boundary = (t_exit - t_enter) * ray_length;
+        let hit_distance = self.neg_inv_density * random_f64().ln();
+
+        if hit_distance > distance_inside_boundary {
+            return false;
+        }
+
+        rec.t = t_enter + hit_distance / ray_length;
+        rec.p = r.at(rec.t);
+        rec.u = 0.;
+        rec.v = 0.;
+        rec.normal = Vec3::new(1., 0., 0.); //arbitrary
+        rec.front_face = true; //also arbitrary
+        rec.mat = self.phase_function.clone();
+
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.boundary.bounding_box()
+    }
+}
@@ -0,0 +1,100 @@
+use crate::aabb::*;
+use crate::hittable::*;
+use crate::material::*;
+use crate::onb::*;
+use crate::ray::*;
+use crate::utility::*;
+use crate::vec3::*;
+use crate::Interval;
+use std::sync::Arc;
+
+//flat disk facing along normal, u is the angle around the center and v the distance from it
+pub struct Disk {
+    pub center: Point3,
+    pub normal: Vec3,
+    pub radius: f64,
+    material: Arc<dyn Material>,
+    bbox: Aabb,
+    d: f64,
+    axis_u: Vec3, //in-plane axes measuring the angle for u
+    axis_v: Vec3,
+}
+
+impl Disk {
+    pub fn new(center: Point3, normal: Vec3, radius: f64, material: Arc<dyn Material>) -> Self {
+        let normal = normal.unit();
+        let d = normal.dot(&center);
+
+        let Onb {
+            u: axis_u,
+            v: axis_v,
+            ..
+        } = Onb::new(&normal);
+
+        //a disk reaches radius * sin(angle between normal and axis) along each axis
+        let extent = |n: f64| radius * (1. - n * n).max(0.).sqrt();
+        let rvec = Vec3::new(extent(normal.x), extent(normal.y), extent(normal.z));
+        let bbox = Aabb::new_from_points(&(&center - &rvec), &(&center + &rvec)).pad();
+
+        Self {
+            center,
+            normal,
+            radius,
+            material,
+            bbox,
+            d,
+            axis_u,
+            axis_v,
+        }
+    }
+}
+
+impl Hittable for Disk {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let denom = self.normal.dot(&r.direction);
+
+        //no hit if the ray is parallel to the plane
+        if denom.abs() < 1e-8 {
+            return false;
+        }
+
+        let t = (self.d - self.normal.dot(&r.origin)) / denom;
+        if !ray_t.surrounds(t) {
+            return false;
+        }
+
+        let intersection = r.at(t);
+        let offset = &intersection - &self.center;
+        let distance_squared = offset.length_squared();
+        if distance_squared > self.radius * self.radius {
+            return false;
+        }
+
+        let phi = offset.dot(&self.axis_v).atan2(offset.dot(&self.axis_u)) + PI;
+
+        rec.t = t;
+        rec.p = intersection;
+        rec.u = phi / (2. * PI);
+        rec.v = distance_squared.sqrt() / self.radius;
+        rec.mat = self.material.clone();
+        rec.set_face_normal(r, &self.normal);
+
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        surface_pdf_value(self, PI * self.radius * self.radius, origin, direction)
+    }
+
+    //uniform over the area, the square root keeps the outer rings from getting too few points
+    fn random(&self, origin: &Point3) -> Vec3 {
+        let rho = self.radius * random_f64().sqrt();
+        let phi = 2. * PI * random_f64();
+        let p = &self.center + rho * phi.cos() * &self.axis_u + rho * phi.sin() * &self.axis_v;
+        p - origin
+    }
+}
@@ -1,5 +1,7 @@
 use crate::camera::*;
+use crate::hdr::*;
 use crate::hittable_list::*;
+use crate::tonemap::*;
 use crate::utility::*;
 use crate::Image;
 use crate::Vec3;
@@ -9,6 +11,9 @@ use druid::*;
 use im::vector;
 use im::Vector;
 
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::thread;
@@ -18,18 +23,50 @@ use std::time::Instant;
 #[derive(Data, Clone, Lens)]
 struct AppState {
     image_buf: ImageBuf,
+    accumulation: Arc<HdrImage>, //running average shown in the window, tone mapped on change
+    accumulated_samples: usize,
+    accumulated_cam: Camera, //view the accumulation belongs to
+    accumulated_world: HittableList,
+    samples_per_pass: usize,
     cam: Camera,
     world: HittableList,
+    lights: HittableList, //emitters sampled directly, part of world as well
     time_elapsed: Duration,
     continous_render: bool,
+    progressive: bool,
+    //set on every camera or sample count change, the render thread waits for a tick without
+    //one before it completes the render, so dragging only renders previews
+    view_changed: bool,
+    dragging: bool, //a mouse drag on the image or a slider, the full render waits for it to end
+    //bumped whenever the accumulation starts over, passes of older generations are cancelled
+    //and their results dropped
+    generation: u64,
+    current_generation: Arc<AtomicU64>, //generation as seen by the pass threads
+    pass_in_flight: bool,               //only one pass renders at a time
+    #[data(ignore)]
+    event_sink: Option<ExtEventSink>, //where pass threads send their results
+
+    orbit_distance: f64, //radius of the continous rendering orbit around lookat
+    orbit_speed: f64,    //radians per continous render
+    status: String,
 }
 
 unsafe impl Send for AppState {}
 
+//a pass rendered off the UI thread, sent back as PASS_RENDERED
+struct RenderedPass {
+    generation: u64,
+    pass: Option<HdrImage>, //None when it was cancelled
+    samples: usize,
+    time_elapsed: Duration,
+}
+
+const PASS_RENDERED: Selector<SingleUse<RenderedPass>> = Selector::new("ray_tracer.pass-rendered");
+
 impl AppState {
     pub fn update_image(&mut self) {
-        let distance = 5.;
-        let da = 0.2;
+        let distance = self.orbit_distance;
+        let da = self.orbit_speed;
         let cam = &self.cam;
         let lookfrom = &cam.lookfrom;
         let lookat = &cam.lookat;
@@ -39,7 +76,7 @@ impl AppState {
 
         let mut angle = dz.atan2(dx);
 
-        angle = angle + da;
+        angle += da;
 
         // println!("angle: {}",angle);
 
@@ -52,16 +89,352 @@ impl AppState {
         // let prev_loc = &self.cam.lookfrom;
         // self.cam.lookfrom = prev_loc + 0.01 * prev_loc;
 
-        let time_start = Instant::now();
-        let img = self.cam.parallel_render(&self.world);
-        let time_end = Instant::now();
+        //starts over even when the orbit left the camera where it was
+        self.reset_accumulation();
+        self.add_samples(self.cam.samples_per_pixel);
+    }
+
+    //adds one pass of samples_per_pass samples to the running average, until the camera's
+    //samples_per_pixel is reached, starting over if the view or the world changed. Passes
+    //render on their own thread and are added by finish_pass
+    pub fn refine_image(&mut self) {
+        self.add_samples(self.samples_per_pass);
+    }
+
+    //renders whatever samples_per_pixel still lacks in one pass, what the view settles on
+    //when progressive mode is off
+    pub fn complete_image(&mut self) {
+        self.add_samples(usize::MAX);
+    }
+
+    //starts the pass the mode calls for: progressive mode keeps refining, otherwise views
+    //that are still changing get previews and the full render waits until they settle
+    pub fn next_pass(&mut self) {
+        if self.continous_render {
+            return;
+        }
+        if self.progressive || self.dragging || self.view_changed {
+            self.refine_image();
+        } else {
+            self.complete_image();
+        }
+    }
 
-        // println!("Time start: {:?}", time_start);
-        // println!("Time end: {:?}", time_end);
-        let time_elapsed = time_end - time_start;
-        println!("Time elapsed: {:?}", time_elapsed);
-        self.image_buf = img.into();
+    fn add_samples(&mut self, samples_per_pass: usize) {
+        if !self.cam.same_view(&self.accumulated_cam)
+            || !self.world.objects.ptr_eq(&self.accumulated_world.objects)
+        {
+            self.reset_accumulation();
+        }
+        if self.accumulated_samples >= self.cam.samples_per_pixel || self.rendering() {
+            return;
+        }
+        let Some(event_sink) = self.event_sink.clone() else {
+            return;
+        };
+
+        let samples = samples_per_pass.min(self.cam.samples_per_pixel - self.accumulated_samples);
+        let generation = self.generation;
+        let current_generation = self.current_generation.clone();
+        let mut cam = self.cam.clone();
+        let world = self.world.clone();
+        let lights = self.lights.clone();
+        self.pass_in_flight = true;
+        thread::spawn(move || {
+            let time_start = Instant::now();
+            let pass = cam.parallel_render_pass_cancellable(&world, &lights, samples, || {
+                current_generation.load(Ordering::Relaxed) != generation
+            });
+            let rendered = RenderedPass {
+                generation,
+                pass,
+                samples,
+                time_elapsed: time_start.elapsed(),
+            };
+            let _ =
+                event_sink.submit_command(PASS_RENDERED, SingleUse::new(rendered), Target::Auto);
+        });
+    }
+
+    //adds a pass from its thread to the running average, unless the accumulation started
+    //over since it began, then starts the next one right away
+    fn finish_pass(&mut self, rendered: RenderedPass) {
+        self.pass_in_flight = false;
+        match rendered.pass {
+            Some(pass) if rendered.generation == self.generation => {
+                self.add_pass(pass, rendered.samples, rendered.time_elapsed)
+            }
+            //cancelled or stale, the view it was for is gone
+            _ => {}
+        }
+        self.next_pass();
+    }
+
+    fn add_pass(&mut self, pass: HdrImage, samples: usize, time_elapsed: Duration) {
         self.time_elapsed = time_elapsed;
+
+        //a new Arc so druid sees the change
+        self.accumulation = Arc::new(if self.accumulated_samples == 0 {
+            pass
+        } else {
+            let mut running = (*self.accumulation).clone();
+            running.accumulate(&pass, self.accumulated_samples, samples);
+            running
+        });
+        self.accumulated_samples += samples;
+        self.apply_tone_map();
+    }
+
+    //a pass started before the view last changed still counts until it has given up
+    pub fn rendering(&self) -> bool {
+        self.pass_in_flight
+    }
+
+    //cancels the pass being rendered, the next one starts from no samples
+    pub fn reset_accumulation(&mut self) {
+        self.accumulated_samples = 0;
+        self.accumulated_cam = self.cam.clone();
+        self.accumulated_world = self.world.clone();
+        self.generation += 1;
+        self.current_generation
+            .store(self.generation, Ordering::Relaxed);
+    }
+
+    pub fn apply_tone_map(&mut self) {
+        self.image_buf = self.cam.tone_map.apply(&self.accumulation).into();
+    }
+
+    //writes the frame currently shown, the format follows the extension
+    pub fn save_image(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        if self.accumulated_samples == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "nothing has been rendered yet",
+            ));
+        }
+        self.cam.tone_map.save(&self.accumulation, path)
+    }
+}
+
+const SAVE_TYPES: [FileSpec; 5] = [
+    FileSpec::new("PNG image", &["png"]),
+    FileSpec::new("PPM image", &["ppm"]),
+    FileSpec::new("Radiance HDR", &["hdr"]),
+    FileSpec::new("Portable float map", &["pfm"]),
+    FileSpec::new("OpenEXR", &["exr"]),
+];
+
+fn save_dialog_options() -> FileDialogOptions {
+    FileDialogOptions::new()
+        .allowed_types(SAVE_TYPES.to_vec())
+        .default_type(SAVE_TYPES[0])
+        .default_name("render.png")
+        .title("Save rendered image")
+}
+
+//ctrl+s opens the save dialog from anywhere in the window, the chosen path comes back as
+//SAVE_FILE_AS
+struct Delegate;
+
+impl AppDelegate<AppState> for Delegate {
+    fn event(
+        &mut self,
+        ctx: &mut DelegateCtx,
+        window_id: WindowId,
+        event: Event,
+        _data: &mut AppState,
+        _env: &Env,
+    ) -> Option<Event> {
+        if let Event::KeyDown(key) = &event {
+            if HotKey::new(SysMods::Cmd, "s").matches(key) {
+                ctx.submit_command(
+                    commands::SHOW_SAVE_PANEL
+                        .with(save_dialog_options())
+                        .to(window_id),
+                );
+                return None;
+            }
+        }
+        Some(event)
+    }
+
+    fn command(
+        &mut self,
+        _ctx: &mut DelegateCtx,
+        _target: Target,
+        cmd: &Command,
+        data: &mut AppState,
+        _env: &Env,
+    ) -> Handled {
+        if let Some(rendered) = cmd.get(PASS_RENDERED) {
+            if let Some(rendered) = rendered.take() {
+                data.finish_pass(rendered);
+            }
+            return Handled::Yes;
+        }
+        if let Some(file) = cmd.get(commands::SAVE_FILE_AS) {
+            data.status = match data.save_image(file.path()) {
+                Ok(()) => format!("Saved {}", file.path().display()),
+                Err(err) => format!("Save failed: {}", err),
+            };
+            return Handled::Yes;
+        }
+        Handled::No
+    }
+}
+
+//previews a re-render when one of the wrapped controls changes the camera, or only re-maps
+//the last render when just the tone mapping changed. A slider drag gets the full render once
+//it is let go
+struct SettingsController;
+
+impl<W: Widget<AppState>> Controller<AppState, W> for SettingsController {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut AppState,
+        env: &Env,
+    ) {
+        let before = data.cam.clone();
+        if let Event::MouseDown(_) = event {
+            data.dragging = true;
+        }
+        child.event(ctx, event, data, env);
+        if !data.cam.same_view(&before) || data.cam.samples_per_pixel != before.samples_per_pixel {
+            data.refine_image();
+            data.view_changed = true;
+        } else if data.cam.tone_map != before.tone_map {
+            data.apply_tone_map();
+        }
+        if let Event::MouseUp(_) = event {
+            data.dragging = false;
+            data.view_changed = false;
+            data.next_pass();
+        }
+    }
+}
+
+//mouse and keyboard camera controls on the image: left drag orbits, middle drag (or shift
+//left drag) pans, the wheel zooms the field of view (dollies with ctrl), WASD/QE fly
+#[derive(Default)]
+struct NavigationController {
+    drag: Option<(MouseButton, Point)>,
+}
+
+impl NavigationController {
+    const ORBIT_DEGREES_PER_PIXEL: f64 = 0.4;
+    const ZOOM_STEP: f64 = 1.1;
+    //pan and fly steps are relative to the distance to lookat
+    const PAN_PER_PIXEL: f64 = 0.002;
+    const FLY_STEP: f64 = 0.1;
+}
+
+impl<W: Widget<AppState>> Controller<AppState, W> for NavigationController {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut AppState,
+        env: &Env,
+    ) {
+        let cam = &mut data.cam;
+        let distance = (&cam.lookfrom - &cam.lookat).length();
+        let before = cam.clone();
+        let mut drag_ended = false;
+
+        match event {
+            Event::MouseDown(mouse) => {
+                ctx.request_focus();
+                ctx.set_active(true);
+                let button = if mouse.button.is_left() && mouse.mods.shift() {
+                    MouseButton::Middle
+                } else {
+                    mouse.button
+                };
+                self.drag = Some((button, mouse.pos));
+                data.dragging = true;
+            }
+            Event::MouseMove(mouse) => {
+                if let Some((button, last)) = self.drag {
+                    let delta = mouse.pos - last;
+                    match button {
+                        MouseButton::Left => cam.orbit(
+                            -delta.x * Self::ORBIT_DEGREES_PER_PIXEL,
+                            delta.y * Self::ORBIT_DEGREES_PER_PIXEL,
+                        ),
+                        MouseButton::Middle => {
+                            let step = Self::PAN_PER_PIXEL * distance;
+                            cam.pan(-delta.x * step, delta.y * step)
+                        }
+                        _ => {}
+                    }
+                    self.drag = Some((button, mouse.pos));
+                }
+            }
+            Event::MouseUp(_) => {
+                ctx.set_active(false);
+                self.drag = None;
+                drag_ended = true;
+            }
+            Event::Wheel(mouse) => {
+                let factor = if mouse.wheel_delta.y > 0. {
+                    Self::ZOOM_STEP
+                } else {
+                    1. / Self::ZOOM_STEP
+                };
+                if mouse.mods.ctrl() {
+                    cam.dolly(factor);
+                } else {
+                    cam.zoom(factor);
+                }
+            }
+            Event::KeyDown(key) => {
+                let step = Self::FLY_STEP * distance;
+                if let KbKey::Character(character) = &key.key {
+                    match character.to_lowercase().as_str() {
+                        "w" => cam.fly(step, 0., 0.),
+                        "s" => cam.fly(-step, 0., 0.),
+                        "a" => cam.fly(0., -step, 0.),
+                        "d" => cam.fly(0., step, 0.),
+                        "q" => cam.fly(0., 0., -step),
+                        "e" => cam.fly(0., 0., step),
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        if !cam.same_view(&before) {
+            //a quick low sample preview, refined or completed once the view settles
+            data.refine_image();
+            data.view_changed = true;
+            ctx.set_handled();
+        }
+        if drag_ended {
+            //the view has settled, no need to wait for the next tick
+            data.dragging = false;
+            data.view_changed = false;
+            data.next_pass();
+        }
+        child.event(ctx, event, data, env)
+    }
+
+    fn lifecycle(
+        &mut self,
+        child: &mut W,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        data: &AppState,
+        env: &Env,
+    ) {
+        if let LifeCycle::BuildFocusChain = event {
+            ctx.register_for_focus();
+        }
+        child.lifecycle(ctx, event, data, env)
     }
 }
 
@@ -137,9 +510,22 @@ fn build_ui(app_state: &AppState, cam: &mut Camera, world: &HittableList) -> imp
             Flex::row()
                 .with_flex_child(
                     Flex::column()
-                        .with_child(Rebuilder::new().center())
+                        .with_child(
+                            Rebuilder::new()
+                                .controller(NavigationController::default())
+                                .center(),
+                        )
                         .with_child(Label::dynamic(|app_state: &AppState, _| {
                             format!("Frame time: {:?}", app_state.time_elapsed)
+                        }))
+                        .with_child(Label::dynamic(|app_state: &AppState, _| {
+                            format!(
+                                "Samples: {}/{}",
+                                app_state.accumulated_samples, app_state.cam.samples_per_pixel
+                            )
+                        }))
+                        .with_child(Label::dynamic(|app_state: &AppState, _| {
+                            app_state.status.clone()
                         })), FlexParams::new(1.0, CrossAxisAlignment::Fill)
                 )
                 .with_child(
@@ -153,8 +539,29 @@ fn build_ui(app_state: &AppState, cam: &mut Camera, world: &HittableList) -> imp
                             ),
                         )
                         .with_spacer(10.)
+                        .with_child(Button::new("Save").on_click(|ctx, _, _| {
+                            ctx.submit_command(
+                                commands::SHOW_SAVE_PANEL.with(save_dialog_options()),
+                            );
+                        }))
+                        .with_spacer(10.)
                         .with_child(Label::new("Continous rendering"))
-                        .with_child(LensWrap::new(Switch::new(), AppState::continous_render)),
+                        .with_child(LensWrap::new(Switch::new(), AppState::continous_render))
+                        .with_spacer(10.)
+                        .with_child(Label::new("Progressive"))
+                        .with_child(LensWrap::new(Switch::new(), AppState::progressive))
+                        .with_spacer(10.)
+                        .with_flex_child(
+                            Scroll::new(
+                                Flex::column()
+                                    .with_child(build_camera_controls())
+                                    .with_spacer(10.)
+                                    .with_child(build_tone_map_controls())
+                                    .controller(SettingsController),
+                            )
+                            .vertical(),
+                            1.,
+                        ),
                 )
                 .cross_axis_alignment(CrossAxisAlignment::Center)
                 .main_axis_alignment(MainAxisAlignment::Center),
@@ -162,6 +569,126 @@ fn build_ui(app_state: &AppState, cam: &mut Camera, world: &HittableList) -> imp
         .padding(10.0)
 }
 
+fn build_tone_map_controls() -> impl Widget<AppState> {
+    let operator = lens::Map::new(
+        |app_state: &AppState| app_state.cam.tone_map.operator,
+        |app_state: &mut AppState, operator| app_state.cam.tone_map.operator = operator,
+    );
+    let exposure = lens::Map::new(
+        |app_state: &AppState| app_state.cam.tone_map.exposure,
+        |app_state: &mut AppState, exposure| app_state.cam.tone_map.exposure = exposure,
+    );
+
+    Flex::column()
+        .with_child(Label::new("Tone mapping"))
+        .with_child(LensWrap::new(
+            RadioGroup::column(ToneMapOperator::ALL.map(|operator| (operator.name(), operator))),
+            operator,
+        ))
+        .with_spacer(10.)
+        .with_child(Label::dynamic(|app_state: &AppState, _| {
+            format!("Exposure: {:+.1} stops", app_state.cam.tone_map.exposure)
+        }))
+        .with_child(LensWrap::new(
+            Slider::new().with_range(-6., 6.).with_step(0.5),
+            exposure,
+        ))
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+}
+
+//label showing the current value above a slider over range
+fn slider_row(
+    name: &'static str,
+    range: (f64, f64),
+    step: f64,
+    lens: impl Lens<AppState, f64> + Clone + 'static,
+) -> impl Widget<AppState> {
+    let label_lens = lens.clone();
+    let decimals = (-step.log10()).ceil().max(0.) as usize;
+    Flex::column()
+        .with_child(Label::dynamic(move |app_state: &AppState, _| {
+            let value = label_lens.with(app_state, |value| *value);
+            format!("{}: {:.*}", name, decimals, value)
+        }))
+        .with_child(LensWrap::new(
+            Slider::new().with_range(range.0, range.1).with_step(step),
+            lens,
+        ))
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+}
+
+//counts shown as whole numbers on an f64 slider
+fn count_lens(
+    lens: impl Lens<Camera, usize> + Clone + 'static,
+) -> impl Lens<AppState, f64> + Clone {
+    AppState::cam.then(lens.map(
+        |count: &usize| *count as f64,
+        |count: &mut usize, value: f64| *count = value.round().max(1.) as usize,
+    ))
+}
+
+fn build_camera_controls() -> impl Widget<AppState> {
+    Flex::column()
+        .with_child(Label::new("Camera"))
+        .with_child(slider_row(
+            "Image width",
+            (50., 1600.),
+            10.,
+            count_lens(Camera::image_width),
+        ))
+        .with_child(slider_row(
+            "Aspect ratio",
+            (0.5, 3.),
+            0.05,
+            AppState::cam.then(Camera::aspect_ratio),
+        ))
+        .with_child(slider_row(
+            "Samples per pixel",
+            (1., 500.),
+            1.,
+            count_lens(Camera::samples_per_pixel),
+        ))
+        .with_child(slider_row(
+            "Max depth",
+            (1., 100.),
+            1.,
+            count_lens(Camera::max_depth),
+        ))
+        .with_child(slider_row(
+            "Vertical fov",
+            (1., 179.),
+            1.,
+            AppState::cam.then(Camera::vfov),
+        ))
+        .with_child(slider_row(
+            "Defocus angle",
+            (0., 10.),
+            0.1,
+            AppState::cam.then(Camera::defocus_angle),
+        ))
+        .with_child(slider_row(
+            "Focus distance",
+            (0.1, 50.),
+            0.1,
+            AppState::cam.then(Camera::focus_dist),
+        ))
+        .with_spacer(10.)
+        .with_child(Label::new("Continous rendering orbit"))
+        .with_child(slider_row(
+            "Radius",
+            (0.5, 50.),
+            0.5,
+            AppState::orbit_distance,
+        ))
+        .with_child(slider_row(
+            "Speed (radians per frame)",
+            (-1., 1.),
+            0.05,
+            AppState::orbit_speed,
+        ))
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+}
+
 fn continous_rendering(event_sink: druid::ExtEventSink) {
     thread::sleep(Duration::from_millis(250));
 
@@ -171,24 +698,47 @@ fn continous_rendering(event_sink: druid::ExtEventSink) {
     loop {
         event_sink.add_idle_callback(move |data: &mut AppState| {
             if data.continous_render {
-                data.update_image();
+                //the next frame starts once the last one is shown
+                if !data.rendering() {
+                    data.update_image();
+                }
+            } else {
+                data.next_pass();
+                //a view that hasn't changed since the last tick has settled
+                data.view_changed = false;
             }
         });
         thread::sleep(Duration::from_millis(250));
     }
 }
 
-pub fn display_image(cam: &mut Camera, world: &HittableList) {
+pub fn display_image(cam: &mut Camera, world: &HittableList, lights: &HittableList) {
     // let time_start = Instant::now();
     // let img = render(cam, world);
     // let time_end = Instant::now();
     let img = ImageBuf::empty();
-    let initial_data = AppState {
-        image_buf: img.into(),
+    let mut initial_data = AppState {
+        image_buf: img,
+        accumulation: Arc::new(HdrImage::new(0, 0)),
+        accumulated_samples: 0,
+        accumulated_cam: cam.clone(),
+        accumulated_world: world.clone(),
+        samples_per_pass: 1,
         cam: cam.clone(),
         world: world.clone(),
+        lights: lights.clone(),
         time_elapsed: Duration::new(0, 0),
         continous_render: false,
+        progressive: false,
+        view_changed: false,
+        dragging: false,
+        generation: 0,
+        current_generation: Arc::new(AtomicU64::new(0)),
+        pass_in_flight: false,
+        event_sink: None,
+        orbit_distance: 5.,
+        orbit_speed: 0.2,
+        status: String::new(),
     };
 
     // println!("Time start: {:?}", time_start);
@@ -199,9 +749,10 @@ pub fn display_image(cam: &mut Camera, world: &HittableList) {
         .window_size((900.0, 600.0))
         .title("Ray tracing in one weekend");
 
-    let launcher = AppLauncher::with_window(main_window);
+    let launcher = AppLauncher::with_window(main_window).delegate(Delegate);
 
     let eventsink = launcher.get_external_handle();
+    initial_data.event_sink = Some(eventsink.clone());
 
     thread::spawn(move || {
         continous_rendering(eventsink);
@@ -0,0 +1,252 @@
+use crate::image::*;
+use crate::tonemap::*;
+use crate::utility::*;
+use crate::vec3::*;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HdrFormat {
+    //Radiance RGBE
+    Hdr,
+    //portable float map
+    Pfm,
+    //OpenEXR with 32-bit float channels
+    Exr,
+}
+
+impl HdrFormat {
+    //None for extensions that can't hold float data
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match extension(path).as_deref() {
+            Some("hdr") => Some(HdrFormat::Hdr),
+            Some("pfm") => Some(HdrFormat::Pfm),
+            Some("exr") => Some(HdrFormat::Exr),
+            _ => None,
+        }
+    }
+}
+
+//linear radiance per pixel, row-major with the top row first, nothing is clamped
+#[derive(Clone, Debug, PartialEq)]
+pub struct HdrImage {
+    width: usize,
+    height: usize,
+    pixels: Vec<[f32; 3]>,
+}
+
+impl HdrImage {
+    pub fn new(height: usize, width: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![[0.; 3]; width * height],
+        }
+    }
+
+    pub fn new_with_init(
+        height: usize,
+        width: usize,
+        init: impl Fn(usize, usize) -> Color,
+    ) -> Self {
+        let mut image = Self::new(height, width);
+        for row in 0..height {
+            for col in 0..width {
+                image.set(row, col, &init(row, col));
+            }
+        }
+        image
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> Color {
+        let [r, g, b] = self.pixels[row * self.width + col];
+        Color::new(r as f64, g as f64, b as f64)
+    }
+
+    pub fn set(&mut self, row: usize, col: usize, color: &Color) {
+        self.pixels[row * self.width + col] = [color.x as f32, color.y as f32, color.z as f32];
+    }
+
+    //folds the average of another pass into this running average, weighting each by its
+    //sample count
+    pub fn accumulate(&mut self, pass: &HdrImage, samples_so_far: usize, pass_samples: usize) {
+        assert_eq!(
+            (self.width, self.height),
+            (pass.width, pass.height),
+            "accumulated passes must have the same size"
+        );
+        let weight = pass_samples as f32 / (samples_so_far + pass_samples) as f32;
+        for (average, sample) in self.pixels.iter_mut().zip(&pass.pixels) {
+            for c in 0..3 {
+                average[c] += (sample[c] - average[c]) * weight;
+            }
+        }
+    }
+
+    //8-bit sRGB with the default tone mapping, clamping everything above 1
+    pub fn to_image(&self) -> Image {
+        ToneMap::default().apply(self)
+    }
+
+    //picks the encoder from the file extension
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        match HdrFormat::from_path(path) {
+            Some(HdrFormat::Hdr) => fs::write(path, self.to_hdr()),
+            Some(HdrFormat::Pfm) => fs::write(path, self.to_pfm()),
+            Some(HdrFormat::Exr) => {
+                exr::prelude::write_rgb_file(path, self.width, self.height, |x, y| {
+                    let [r, g, b] = self.pixels[y * self.width + x];
+                    (r, g, b)
+                })
+                .map_err(io::Error::other)
+            }
+            None => Err(unsupported_format("HDR image", path)),
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        match HdrFormat::from_path(path) {
+            Some(HdrFormat::Pfm) => Self::from_pfm(&fs::read(path)?),
+            Some(HdrFormat::Exr) => {
+                let image = exr::prelude::read_first_rgba_layer_from_file(
+                    path,
+                    |resolution, _| Self::new(resolution.height(), resolution.width()),
+                    |image: &mut Self, position, (r, g, b, _a): (f32, f32, f32, f32)| {
+                        image.pixels[position.y() * image.width + position.x()] = [r, g, b];
+                    },
+                )
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+                Ok(image.layer_data.channel_data.pixels)
+            }
+            _ => Err(unsupported_format("HDR image", path)),
+        }
+    }
+
+    //Radiance .hdr, scanlines use the run-length format with literal runs only
+    //so that no pixel can be mistaken for a run-length marker
+    pub fn to_hdr(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let _ = write!(
+            bytes,
+            "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+            self.height, self.width
+        );
+
+        let run_length = (8..0x8000).contains(&self.width);
+        for row in self.pixels.chunks(self.width.max(1)) {
+            let rgbe: Vec<[u8; 4]> = row.iter().map(to_rgbe).collect();
+            if !run_length {
+                bytes.extend(rgbe.iter().flatten());
+                continue;
+            }
+
+            bytes.extend([2, 2, (self.width >> 8) as u8, (self.width & 0xff) as u8]);
+            for component in 0..4 {
+                for chunk in rgbe.chunks(128) {
+                    bytes.push(chunk.len() as u8);
+                    bytes.extend(chunk.iter().map(|pixel| pixel[component]));
+                }
+            }
+        }
+        bytes
+    }
+
+    //little-endian color PFM, rows are stored bottom to top
+    pub fn to_pfm(&self) -> Vec<u8> {
+        let mut bytes = format!("PF\n{} {}\n-1.0\n", self.width, self.height).into_bytes();
+        for row in self.pixels.chunks(self.width.max(1)).rev() {
+            for value in row.iter().flatten() {
+                bytes.extend(value.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    pub fn from_pfm(bytes: &[u8]) -> io::Result<Self> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        //the header is three whitespace separated tokens after the magic
+        let mut tokens = Vec::new();
+        let mut pos = 0;
+        while tokens.len() < 4 {
+            while bytes.get(pos).is_some_and(u8::is_ascii_whitespace) {
+                pos += 1;
+            }
+            let start = pos;
+            while bytes
+                .get(pos)
+                .is_some_and(|byte| !byte.is_ascii_whitespace())
+            {
+                pos += 1;
+            }
+            if start == pos {
+                return Err(invalid("PFM header is truncated"));
+            }
+            tokens.push(String::from_utf8_lossy(&bytes[start..pos]).to_string());
+        }
+        //a single whitespace byte separates the header from the raster
+        pos += 1;
+
+        if tokens[0] != "PF" {
+            return Err(invalid("not a color PFM file"));
+        }
+        let parse = |token: &str| token.parse().map_err(|_| invalid("malformed PFM header"));
+        let width: usize = parse(&tokens[1])?;
+        let height: usize = parse(&tokens[2])?;
+        let little_endian = tokens[3].starts_with('-');
+
+        //the header is untrusted, its size must not overflow before the length check
+        let raster_end = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(12))
+            .and_then(|raster_len| raster_len.checked_add(pos))
+            .ok_or_else(|| invalid("PFM dimensions too large"))?;
+        let raster = bytes
+            .get(pos..raster_end)
+            .ok_or_else(|| invalid("PFM raster is truncated"))?;
+        let values: Vec<f32> = raster
+            .chunks(4)
+            .map(|chunk| {
+                let chunk = [chunk[0], chunk[1], chunk[2], chunk[3]];
+                if little_endian {
+                    f32::from_le_bytes(chunk)
+                } else {
+                    f32::from_be_bytes(chunk)
+                }
+            })
+            .collect();
+
+        Ok(Self::new_with_init(height, width, |row, col| {
+            let i = 3 * ((height - 1 - row) * width + col);
+            Color::new(values[i] as f64, values[i + 1] as f64, values[i + 2] as f64)
+        }))
+    }
+}
+
+//shared exponent encoding, components are stored as mantissas of the largest one
+fn to_rgbe(&[r, g, b]: &[f32; 3]) -> [u8; 4] {
+    let max = r.max(g).max(b);
+    if max < 1e-32 {
+        return [0; 4];
+    }
+    let exponent = max.log2().floor() as i32 + 1;
+    let scale = 256. / 2f32.powi(exponent);
+    let mantissa = |c: f32| (c.max(0.) * scale).min(255.) as u8;
+    [
+        mantissa(r),
+        mantissa(g),
+        mantissa(b),
+        (exponent + 128) as u8,
+    ]
+}
@@ -1,8 +1,9 @@
+use crate::aabb::*;
 use crate::material::*;
 use crate::ray::*;
 use crate::vec3::*;
-use crate::Interval;
-use std::sync::Arc;
+use crate::{Color, Interval};
+use std::sync::{Arc, OnceLock};
 
 #[derive(Clone)]
 pub struct HitRecord {
@@ -10,13 +11,38 @@ pub struct HitRecord {
     pub normal: Vec3,
     pub mat: Arc<dyn Material>,
     pub t: f64,
+    pub u: f64, //surface coordinates for texture lookup
+    pub v: f64,
     pub front_face: bool,
 }
 
 impl HitRecord {
+    //blank record for hit to fill in, mat is only a placeholder until then
+    pub fn new(mat: Arc<dyn Material>) -> Self {
+        Self {
+            p: Point3::default(),
+            normal: Vec3::default(),
+            mat,
+            t: 0.,
+            u: 0.,
+            v: 0.,
+            front_face: false,
+        }
+    }
+
+    //blank record sharing one placeholder material, for callers that only need the geometry
+    pub fn new_blank() -> Self {
+        static PLACEHOLDER: OnceLock<Arc<dyn Material>> = OnceLock::new();
+        Self::new(
+            PLACEHOLDER
+                .get_or_init(|| Arc::new(Lambertian::new(Color::default())))
+                .clone(),
+        )
+    }
+
     pub fn set_face_normal(&mut self, r: &Ray, outward_normal: &Vec3) {
         //outward_normal is assumed to be unit length
-        self.front_face = r.direction.dot(&outward_normal) < 0.;
+        self.front_face = r.direction.dot(outward_normal) < 0.;
         self.normal = if self.front_face {
             outward_normal.clone()
         } else {
@@ -25,8 +51,45 @@ impl HitRecord {
     }
 }
 
-pub trait Hittable: Sync {
+//turns a uniform density over a surface with the given area into the solid angle density
+//seen from r.origin, where rec is the point of that surface r hit
+pub fn area_to_solid_angle(r: &Ray, rec: &HitRecord, area: f64) -> f64 {
+    let distance_squared = rec.t * rec.t * r.direction.length_squared();
+    let cosine = (r.direction.dot(&rec.normal) / r.direction.length()).abs();
+    distance_squared / (cosine * area)
+}
+
+//pdf_value of a flat object that random() samples uniformly over its area
+pub fn surface_pdf_value(
+    object: &dyn Hittable,
+    area: f64,
+    origin: &Point3,
+    direction: &Vec3,
+) -> f64 {
+    let mut rec = HitRecord::new_blank();
+    let r = Ray::new(origin.clone(), direction.clone());
+    if !object.hit(&r, Interval::new_with_init(0.001, f64::INFINITY), &mut rec) {
+        return 0.;
+    }
+
+    area_to_solid_angle(&r, &rec, area)
+}
+
+pub trait Hittable: Send + Sync {
     fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
         false
     }
+
+    fn bounding_box(&self) -> Aabb;
+
+    //solid angle density of random() picking direction from origin, 0 where the object
+    //can't be sampled or direction misses it
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        0.
+    }
+
+    //direction from origin towards a random point of the object
+    fn random(&self, origin: &Point3) -> Vec3 {
+        Vec3::new(1., 0., 0.)
+    }
 }
@@ -1,37 +1,34 @@
+#[cfg(feature = "viewer")]
 use druid::Data;
 use im::Vector;
 
+use crate::aabb::*;
 use crate::hittable::*;
 use crate::interval::*;
 use crate::material::*;
 use crate::ray::*;
+use crate::utility::*;
 use crate::vec3::*;
 use crate::Color;
 use std::sync::Arc;
 
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct HittableList {
     pub objects: Vector<Arc<dyn Hittable>>,
 }
 
+#[cfg(feature = "viewer")]
 impl Data for HittableList{
     fn same(&self, other: &Self) -> bool {
         true
     }
 }
 
-unsafe impl Sync for HittableList {}
-
 impl Hittable for HittableList {
     fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
-        let temp_rec: &mut HitRecord = &mut HitRecord {
-            p: Vec3::default(),
-            normal: Vec3::default(),
-            mat: Arc::new(Lambertian::new(Color::new(0., 0., 0.))),
-            t: 0.,
-            front_face: false,
-        };
+        //every hit overwrites the whole record, cloning only shares the material
+        let temp_rec = &mut rec.clone();
         let mut hit_anything = false;
         let mut closest_so_far = ray_t.max;
 
@@ -49,12 +46,47 @@ impl Hittable for HittableList {
 
         hit_anything
     }
+
+    fn bounding_box(&self) -> Aabb {
+        self.objects.iter().fold(Aabb::default(), |bbox, object| {
+            Aabb::new_from_boxes(&bbox, &object.bounding_box())
+        })
+    }
+
+    //every object is picked with the same probability
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        if self.objects.is_empty() {
+            return 0.;
+        }
+        let weight = 1. / self.objects.len() as f64;
+        self.objects
+            .iter()
+            .map(|object| weight * object.pdf_value(origin, direction))
+            .sum()
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        if self.objects.is_empty() {
+            return Vec3::new(1., 0., 0.);
+        }
+        let index =
+            ((random_f64() * self.objects.len() as f64) as usize).min(self.objects.len() - 1);
+        self.objects[index].random(origin)
+    }
 }
 
 impl HittableList {
-    pub fn new() {}
+    pub fn new() -> Self {
+        Self {
+            objects: Vector::new(),
+        }
+    }
 
-    pub fn new_with_init(&self, object: Arc<dyn Hittable>) {}
+    pub fn new_with_init(object: Arc<dyn Hittable>) -> Self {
+        let mut list = Self::new();
+        list.add(object);
+        list
+    }
 
     pub fn clear(&mut self) {
         self.objects.clear();
@@ -1,8 +1,12 @@
+#[cfg(feature = "viewer")]
 use druid::{piet, ImageBuf};
 
-use crate::{vec3::*, Interval};
+use crate::{tonemap::*, utility::*, vec3::*, Interval};
 use std::{
     fmt::*,
+    fs,
+    io::{self, BufWriter, Cursor, Write},
+    path::Path,
     sync::Arc,
     thread::{self, JoinHandle},
 };
@@ -14,9 +18,22 @@ pub struct Pixel {
     pub b: u8,
 }
 
-impl Into<Vec<u8>> for Pixel {
-    fn into(self) -> Vec<u8> {
-        vec![self.r, self.g, self.b]
+impl Pixel {
+    //back to linear color, undoing the sRGB encoding
+    pub fn to_color(&self) -> Color {
+        let scale = 1. / 255.;
+        Color::new(
+            scale * self.r as f64,
+            scale * self.g as f64,
+            scale * self.b as f64,
+        )
+        .map(srgb_to_linear)
+    }
+}
+
+impl From<Pixel> for Vec<u8> {
+    fn from(pixel: Pixel) -> Self {
+        vec![pixel.r, pixel.g, pixel.b]
     }
 }
 
@@ -24,25 +41,42 @@ pub struct Image {
     pub pixels: Vec<Vec<Pixel>>,
 }
 
-impl Into<Arc<[u8]>> for Image {
-    fn into(self) -> Arc<[u8]> {
-        let bytes: Vec<u8> = self
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageFormat {
+    Ppm,
+    Png,
+}
+
+impl ImageFormat {
+    //None for extensions we can't read or write
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match extension(path).as_deref() {
+            Some("ppm") => Some(ImageFormat::Ppm),
+            Some("png") => Some(ImageFormat::Png),
+            _ => None,
+        }
+    }
+}
+
+impl From<Image> for Arc<[u8]> {
+    fn from(image: Image) -> Self {
+        let bytes: Vec<u8> = image
             .pixels
             .into_iter()
             .flatten()
-            .map(|pixel| -> Vec<u8> {pixel.into()})
-            .flatten()
+            .flat_map(|pixel| -> Vec<u8> { pixel.into() })
             .collect();
         let bytes_arc: Arc<[u8]> = Arc::from(bytes);
         bytes_arc
     }
 }
 
-impl Into<ImageBuf> for Image{
-    fn into(self) -> ImageBuf {
-        let width = self.width();
-        let height = self.height();
-        ImageBuf::from_raw(self, piet::ImageFormat::Rgb, width, height)
+#[cfg(feature = "viewer")]
+impl From<Image> for ImageBuf {
+    fn from(image: Image) -> Self {
+        let width = image.width();
+        let height = image.height();
+        ImageBuf::from_raw(image, piet::ImageFormat::Rgb, width, height)
     }
 }
 
@@ -81,10 +115,203 @@ impl Image {
         self.pixels.len()
     }
     pub fn width(&self) -> usize {
-        self.pixels[0].len()
+        self.pixels.first().map_or(0, |row| row.len())
+    }
+
+    //picks the decoder from the file extension
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        match ImageFormat::from_path(path) {
+            Some(ImageFormat::Ppm) => Self::from_ppm(&fs::read(path)?),
+            Some(ImageFormat::Png) => Self::from_png(&fs::read(path)?),
+            None => Err(unsupported_format("image", path)),
+        }
+    }
+
+    //picks the encoder from the file extension
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let format =
+            ImageFormat::from_path(path).ok_or_else(|| unsupported_format("image", path))?;
+
+        let mut buffer = BufWriter::new(fs::File::create(path)?);
+        match format {
+            ImageFormat::Ppm => write!(buffer, "{}", PPM(self))?,
+            ImageFormat::Png => buffer.write_all(&self.to_png()?)?,
+        }
+        buffer.flush()
+    }
+
+    //row-major RGB bytes, three per pixel
+    pub fn to_rgb_bytes(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flatten()
+            .flat_map(|pixel| [pixel.r, pixel.g, pixel.b])
+            .collect()
+    }
+
+    //8-bit RGB PNG
+    pub fn to_png(&self) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, self.width() as u32, self.height() as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header().map_err(io::Error::other)?;
+        writer
+            .write_image_data(&self.to_rgb_bytes())
+            .map_err(io::Error::other)?;
+        writer.finish().map_err(io::Error::other)?;
+        Ok(bytes)
+    }
+
+    //any bit depth and color type, alpha is dropped
+    pub fn from_png(bytes: &[u8]) -> io::Result<Self> {
+        let mut decoder = png::Decoder::new(Cursor::new(bytes));
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder
+            .read_info()
+            .map_err(|error| invalid_data(&error.to_string()))?;
+
+        let buffer_size = reader
+            .output_buffer_size()
+            .ok_or_else(|| invalid_data("PNG is too large"))?;
+        let mut buffer = vec![0; buffer_size];
+        let info = reader
+            .next_frame(&mut buffer)
+            .map_err(|error| invalid_data(&error.to_string()))?;
+
+        let channels = match info.color_type {
+            png::ColorType::Grayscale => 1,
+            png::ColorType::GrayscaleAlpha => 2,
+            png::ColorType::Rgb => 3,
+            png::ColorType::Rgba => 4,
+            png::ColorType::Indexed => return Err(invalid_data("unexpanded PNG palette")),
+        };
+
+        Ok(Self::new_with_init(
+            info.height as usize,
+            info.width as usize,
+            |row, col| {
+                let i = row * info.line_size + col * channels;
+                if channels < 3 {
+                    let gray = buffer[i];
+                    Pixel {
+                        r: gray,
+                        g: gray,
+                        b: gray,
+                    }
+                } else {
+                    Pixel {
+                        r: buffer[i],
+                        g: buffer[i + 1],
+                        b: buffer[i + 2],
+                    }
+                }
+            },
+        ))
+    }
+
+    //reads both ASCII (P3) and binary (P6) PPM
+    pub fn from_ppm(bytes: &[u8]) -> io::Result<Self> {
+        let mut pos = 0;
+        let magic = next_ppm_token(bytes, &mut pos)?;
+        let width = parse_ppm_number(bytes, &mut pos)?;
+        let height = parse_ppm_number(bytes, &mut pos)?;
+        let maxval = parse_ppm_number(bytes, &mut pos)?;
+        if maxval == 0 || maxval > 65535 {
+            return Err(invalid_data("PPM maxval out of range"));
+        }
+
+        let sample_size = if maxval < 256 { 1 } else { 2 };
+        let count = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(3))
+            .ok_or_else(|| invalid_data("PPM dimensions too large"))?;
+        //checked before allocating, a crafted header can't ask for more than the file holds
+        let smallest_raster = match magic {
+            //at least one digit per sample, with whitespace between them
+            b"P3" => count.saturating_mul(2).saturating_sub(1),
+            //plus the whitespace byte after the header
+            b"P6" => count.saturating_mul(sample_size).saturating_add(1),
+            _ => return Err(invalid_data("not a P3 or P6 PPM file")),
+        };
+        if smallest_raster > bytes.len() - pos {
+            return Err(invalid_data("PPM raster is truncated"));
+        }
+
+        let mut samples = Vec::with_capacity(count);
+        match magic {
+            b"P3" => {
+                for _ in 0..count {
+                    samples.push(parse_ppm_number(bytes, &mut pos)?);
+                }
+            }
+            b"P6" => {
+                //a single whitespace byte separates the header from the raster
+                pos += 1;
+                let raster = bytes
+                    .get(pos..pos + sample_size * count)
+                    .ok_or_else(|| invalid_data("PPM raster is truncated"))?;
+                samples.extend(raster.chunks(sample_size).map(|sample| {
+                    sample
+                        .iter()
+                        .fold(0usize, |value, &byte| (value << 8) | byte as usize)
+                }));
+            }
+            _ => return Err(invalid_data("not a P3 or P6 PPM file")),
+        }
+
+        let to_u8 = |sample: usize| (sample.min(maxval) * 255 / maxval) as u8;
+        Ok(Self::new_with_init(height, width, |row, col| {
+            let i = 3 * (row * width + col);
+            Pixel {
+                r: to_u8(samples[i]),
+                g: to_u8(samples[i + 1]),
+                b: to_u8(samples[i + 2]),
+            }
+        }))
     }
 }
 
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+//whitespace separated header token, skipping # comments
+fn next_ppm_token<'a>(bytes: &'a [u8], pos: &mut usize) -> io::Result<&'a [u8]> {
+    loop {
+        match bytes.get(*pos) {
+            Some(b'#') => {
+                while bytes.get(*pos).is_some_and(|&byte| byte != b'\n') {
+                    *pos += 1;
+                }
+            }
+            Some(byte) if byte.is_ascii_whitespace() => *pos += 1,
+            Some(_) => break,
+            None => return Err(invalid_data("unexpected end of PPM data")),
+        }
+    }
+
+    let start = *pos;
+    while bytes
+        .get(*pos)
+        .is_some_and(|byte| !byte.is_ascii_whitespace())
+    {
+        *pos += 1;
+    }
+    Ok(&bytes[start..*pos])
+}
+
+fn parse_ppm_number(bytes: &[u8], pos: &mut usize) -> io::Result<usize> {
+    let token = next_ppm_token(bytes, pos)?;
+    std::str::from_utf8(token)
+        .ok()
+        .and_then(|token| token.parse().ok())
+        .ok_or_else(|| invalid_data("malformed number in PPM data"))
+}
+
 #[derive(Debug)]
 pub struct PPM<'a, T>(pub &'a T);
 
@@ -135,20 +362,8 @@ impl Color {
         }
     }
 
+    //average of the samples, clamped and sRGB encoded
     pub fn to_pixel_with_spp(&self, samples_per_pixel: usize) -> Pixel {
-        let intensity = Interval::new_with_init(0., 0.999);
-
-        let scale = 1. / samples_per_pixel as f64;
-        let scaled = scale * self;
-
-        fn linear_to_gamma(linear_component: f64) -> f64 {
-            linear_component.sqrt()
-        }
-        let scaled = scaled.map(linear_to_gamma);
-        Pixel {
-            r: (256. * intensity.clamp(scaled.x)) as u8,
-            g: (256. * intensity.clamp(scaled.y)) as u8,
-            b: (256. * intensity.clamp(scaled.z)) as u8,
-        }
+        ToneMap::default().to_pixel(&(self / samples_per_pixel as f64))
     }
 }
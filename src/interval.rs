@@ -1,6 +1,6 @@
 use crate::utility::*;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 pub struct Interval {
     pub min: f64,
     pub max: f64,
@@ -15,6 +15,12 @@ pub static UNIVERSE: Interval = Interval {
     max: INFINITY,
 };
 
+impl Default for Interval {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Interval {
     pub fn new() -> Self {
         Self {
@@ -27,6 +33,23 @@ impl Interval {
         Self { min, max }
     }
 
+    //the tightest interval enclosing both a and b
+    pub fn new_from_intervals(a: &Interval, b: &Interval) -> Self {
+        Self {
+            min: a.min.min(b.min),
+            max: a.max.max(b.max),
+        }
+    }
+
+    pub fn size(&self) -> f64 {
+        self.max - self.min
+    }
+
+    pub fn expand(&self, delta: f64) -> Self {
+        let padding = delta / 2.;
+        Self::new_with_init(self.min - padding, self.max + padding)
+    }
+
     pub fn contains(&self, x: f64) -> bool {
         self.min <= x && x <= self.max
     }
@@ -37,11 +60,11 @@ impl Interval {
 
     pub fn clamp(&self, x: f64) -> f64 {
         if x < self.min {
-            return self.min;
+            self.min
         } else if x > self.max {
-            return self.max;
+            self.max
         } else {
-            return x;
+            x
         }
     }
 }
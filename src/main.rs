@@ -1,34 +1,71 @@
 #![allow(dead_code)]
 #![allow(unused_variables)]
 #![allow(unused_imports)]
+pub mod aabb;
+pub mod bvh;
 pub mod camera;
+pub mod cli;
+pub mod constant_medium;
+pub mod disk;
+#[cfg(feature = "viewer")]
 pub mod display;
+pub mod hdr;
 pub mod hittable;
 pub mod hittable_list;
 pub mod image;
 pub mod interval;
 pub mod material;
+pub mod obj;
+pub mod onb;
+pub mod pdf;
+pub mod perlin;
+pub mod quad;
 pub mod ray;
+pub mod scene;
 pub mod sphere;
+pub mod texture;
+pub mod tonemap;
+pub mod transform;
+pub mod triangle;
+pub mod triangle_mesh;
 pub mod utility;
 pub mod vec3;
 
+use aabb::*;
+use bvh::*;
 use camera::*;
+use cli::*;
+use constant_medium::*;
+use disk::*;
+#[cfg(feature = "viewer")]
 use display::*;
+use hdr::*;
 use hittable::*;
 use hittable_list::*;
 use im::vector;
 use image::*;
 use interval::*;
 use material::*;
+use obj::*;
+use onb::*;
+use pdf::*;
+use perlin::*;
+use quad::*;
 use ray::*;
+use scene::*;
 use sphere::*;
+use texture::*;
+use tonemap::*;
+use transform::*;
+use triangle::*;
+use triangle_mesh::*;
 use utility::*;
 use vec3::*;
 
 use std::{
     fs::File,
     io::{BufWriter, Write},
+    path::PathBuf,
     sync::Arc,
     time::Instant,
 };
@@ -38,69 +75,40 @@ fn main() -> std::io::Result<()> {
     // let mut buffer = BufWriter::new(File::create("sample.ppm")?);
 
     // let img = simple_scene();
-    let mut cam = Camera::default(); //= Camera::new(16./9., 400);
-    cam.aspect_ratio = 16. / 9.;
-    cam.image_width = 200;//800
-    cam.samples_per_pixel = 10;//20
-    cam.max_depth = 10;//50
+    let command = match parse_args(std::env::args().skip(1)) {
+        Ok(command) => command,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(2);
+        }
+    };
 
-    cam.vfov = 20.;
-    cam.lookfrom = Point3::new(-2., 2., 1.);
-    cam.lookat = Point3::new(0., 0., -1.);
-    cam.vup = Vec3::new(0., 1., 0.);
-    let mut world: HittableList = HittableList { objects: vector![] };
-
-    // let r = (PI / 4.).cos();
-
-    // let material_left = Arc::new(Lambertian::new(Color::new(0., 0., 1.)));
-    // let material_right = Arc::new(Lambertian::new(Color::new(1., 0., 0.)));
-
-    // world.add(Arc::new(Sphere::new(
-    //     Point3::new(-r, 0., -1.),
-    //     r,
-    //     material_left,
-    // )));
-    // world.add(Arc::new(Sphere::new(
-    //     Point3::new(r, 0., -1.),
-    //     r,
-    //     material_right,
-    // )));
-
-    let material_ground = Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.0)));
-    let material_center = Arc::new(Lambertian::new(Color::new(0.1, 0.2, 0.5)));
-    let material_left = Arc::new(Dielectric::new(1.5));
-    let material_right = Arc::new(Metal::new(Color::new(0.8, 0.6, 0.2), 0.0));
-
-    world.add(Arc::new(Sphere::new(
-        Point3::new(0., -100.5, -1.),
-        100.,
-        material_ground,
-    )));
-    world.add(Arc::new(Sphere::new(
-        Point3::new(-1., 0., -1.),
-        0.5,
-        material_left.clone(),
-    )));
-    world.add(Arc::new(Sphere::new(
-        Point3::new(-1., 0., -1.),
-        -0.4,
-        material_left,
-    )));
-    world.add(Arc::new(Sphere::new(
-        Point3::new(0., 0., -1.),
-        0.5,
-        material_center,
-    )));
-    world.add(Arc::new(Sphere::new(
-        Point3::new(1., 0., -1.),
-        0.5,
-        material_right,
-    )));
+    //scene file from the command line, or the built-in scene
+    let (scene, base_dir) = match command.scene() {
+        Some(path) => {
+            let base_dir = path.parent().map(PathBuf::from).unwrap_or_default();
+            (SceneDesc::load(path)?, base_dir)
+        }
+        None => (default_scene(), PathBuf::new()),
+    };
+
+    if let Command::Render(options) = &command {
+        return render_to_file(options, &scene, &base_dir);
+    }
+
+    let mut cam = scene.camera.clone();
+    let mut warnings = Vec::new();
+    let (world, lights) = scene.build(&base_dir, &mut warnings)?;
+    for warning in warnings {
+        eprintln!("warning: {}", warning);
+    }
+
+    let world = HittableList::new_with_init(Arc::new(FlatBvh::new(&world, SplitMethod::Sah)));
 
     // display
     // let time_start = Instant::now();
     
-    display_image(&mut cam, &world);
+    display_image(&mut cam, &world, &lights);
 
     // let img = cam.parallel_render(&world);
 
@@ -119,18 +127,80 @@ fn main() -> std::io::Result<()> {
     Ok(())
 }
 
+//built without the viewer feature there is no window to open
+#[cfg(not(feature = "viewer"))]
+fn display_image(cam: &mut Camera, world: &HittableList, lights: &HittableList) {
+    eprintln!("built without the viewer, use `ray_tracer render` to write an image instead");
+    std::process::exit(2);
+}
+
+fn default_scene() -> SceneDesc {
+    let mut cam = Camera::default(); //= Camera::new(16./9., 400);
+    cam.aspect_ratio = 16. / 9.;
+    cam.image_width = 200;//800
+    cam.samples_per_pixel = 10;//20
+    cam.max_depth = 50;
+
+    cam.vfov = 20.;
+    cam.lookfrom = Point3::new(-2., 2., 1.);
+    cam.lookat = Point3::new(0., 0., -1.);
+    cam.vup = Vec3::new(0., 1., 0.);
+
+    let materials = [
+        (
+            "ground",
+            MaterialDesc::Lambertian {
+                albedo: TextureDesc::Solid(Color::new(0.8, 0.8, 0.0)),
+            },
+        ),
+        (
+            "center",
+            MaterialDesc::Lambertian {
+                albedo: TextureDesc::Solid(Color::new(0.1, 0.2, 0.5)),
+            },
+        ),
+        ("left", MaterialDesc::Dielectric { ir: 1.5 }),
+        (
+            "right",
+            MaterialDesc::Metal {
+                albedo: TextureDesc::Solid(Color::new(0.8, 0.6, 0.2)),
+                fuzz: 0.0,
+            },
+        ),
+    ];
+
+    let sphere = |center: Point3, radius: f64, material: &str| {
+        ObjectDesc::new(ShapeDesc::Sphere {
+            center,
+            radius,
+            material: material.to_string(),
+            center_end: None,
+        })
+    };
+
+    SceneDesc {
+        camera: cam,
+        materials: materials
+            .into_iter()
+            .map(|(name, material)| (name.to_string(), material))
+            .collect(),
+        objects: vec![
+            sphere(Point3::new(0., -100.5, -1.), 100., "ground"),
+            sphere(Point3::new(-1., 0., -1.), 0.5, "left"),
+            //hollow glass
+            sphere(Point3::new(-1., 0., -1.), -0.4, "left"),
+            sphere(Point3::new(0., 0., -1.), 0.5, "center"),
+            sphere(Point3::new(1., 0., -1.), 0.5, "right"),
+        ],
+    }
+}
+
 #[cfg(test)]
 mod test_random {
     use super::*;
 
     #[test]
     fn test_hit() {
-        let mut cam = Camera::default(); //= Camera::new(16./9., 400);
-        cam.aspect_ratio = 16. / 9.;
-        cam.image_width = 400;
-        cam.samples_per_pixel = 100;
-        cam.max_depth = 50;
-
         let material_center = Arc::new(Dielectric::new(1.5));
         let ray = Ray::new(Vec3::new(0., 0., 0.), Vec3::new(0.2, 0.2, 0.2));
         let rec: &mut HitRecord = &mut HitRecord {
@@ -138,12 +208,13 @@ mod test_random {
             normal: Vec3::default(),
             mat: Arc::new(Lambertian::new(Color::default())),
             t: 0.,
+            u: 0.,
+            v: 0.,
             front_face: false,
         };
         let mut attenuation = Color::default();
         let mut scattered = Ray::new(Vec3::new(0., 0., 0.), Vec3::new(0., 0., 0.));
-        material_center.scatter(&ray, &rec, &mut attenuation, &mut scattered);
-        // let res = cam.parallel_render(world);
+        material_center.scatter(&ray, rec, &mut attenuation, &mut scattered);
         k9::snapshot!(attenuation, "(1, 1, 1)");
         k9::snapshot!(
             scattered,
@@ -174,10 +245,21 @@ Camera {
     image_width: 400,
     samples_per_pixel: 10,
     max_depth: 50,
+    roulette_depth: 3,
     vfov: 90.0,
     lookfrom: (-2, 2, 1),
     lookat: (0, 0, -1),
     vup: (0, 1, 0),
+    defocus_angle: 0.0,
+    focus_dist: 10.0,
+    shutter_open: 0.0,
+    shutter_close: 1.0,
+    background: None,
+    tone_map: ToneMap {
+        operator: Clamp,
+        exposure: 0.0,
+        white_point: 4.0,
+    },
     image_height: 0,
     center: (0, 0, 0),
     pixel00_loc: (0, 0, 0),
@@ -186,20 +268,2625 @@ Camera {
     u: (0, 0, 0),
     v: (0, 0, 0),
     w: (0, 0, 0),
+    defocus_disk_u: (0, 0, 0),
+    defocus_disk_v: (0, 0, 0),
 }
 "
         );
     }
 }
 #[cfg(test)]
-mod test_hittable {
+mod test_camera {
     use super::*;
 
+    fn focus_camera(defocus_angle: f64) -> Camera {
+        let mut cam = Camera::default();
+        cam.image_width = 100;
+        cam.lookfrom = Point3::new(0., 0., 0.);
+        cam.lookat = Point3::new(0., 0., -1.);
+        cam.focus_dist = 5.;
+        cam.defocus_angle = defocus_angle;
+        cam.initialize();
+        cam
+    }
+
     #[test]
-    fn test_hit() {
-        // let h1 = Hittable::new();
-        let h1 = 2;
-        k9::snapshot!(h1, "2");
+    fn test_pinhole_origin() {
+        let cam = focus_camera(0.);
+        for _ in 0..100 {
+            let r = cam.get_ray(50, 50);
+            k9::snapshot!(r.origin, "(0, 0, 0)");
+        }
+    }
+
+    #[test]
+    fn test_defocus_rays_meet_at_focus_plane() {
+        let cam = focus_camera(10.);
+        let lens_radius = 5. * deg_to_rad(5.).tan();
+        let mut max_offset: f64 = 0.;
+
+        for _ in 0..1000 {
+            let r = cam.get_ray(50, 50);
+            let offset = r.origin.length();
+            assert!(r.origin.z.abs() < 1e-12);
+            assert!(offset < lens_radius);
+            max_offset = max_offset.max(offset);
+
+            //every sample must land inside pixel (50, 50) on the focus plane
+            let focus_point = r.at(-5. / r.direction.z);
+            assert!((focus_point.x - 0.05).abs() <= 0.05 + 1e-9);
+            assert!((focus_point.y + 0.05).abs() <= 0.05 + 1e-9);
+        }
+        assert!(max_offset > 0.5 * lens_radius);
+    }
+}
+
+#[cfg(test)]
+mod test_motion {
+    use super::test_util::*;
+    use super::*;
+
+    #[test]
+    fn test_moving_sphere() {
+        let material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let sphere = Sphere::new_moving(
+            Point3::new(0., 0., -2.),
+            Point3::new(0., 2., -2.),
+            0.5,
+            material,
+        );
+        let ray_t = Interval::new_with_init(0.001, INFINITY);
+        let direction = Vec3::new(0., 0., -1.);
+
+        let early = Ray::new_with_time(Point3::new(0., 0., 0.), direction.clone(), 0.);
+        let late = Ray::new_with_time(Point3::new(0., 0., 0.), direction.clone(), 1.);
+        let late_above = Ray::new_with_time(Point3::new(0., 2., 0.), direction, 1.);
+
+        k9::snapshot!(sphere.hit(&early, ray_t, &mut empty_record()), "true");
+        k9::snapshot!(sphere.hit(&late, ray_t, &mut empty_record()), "false");
+        k9::snapshot!(sphere.hit(&late_above, ray_t, &mut empty_record()), "true");
+        k9::snapshot!(sphere.center_at(0.5), "(0, 1, -2)");
+        k9::snapshot!(
+            sphere.bounding_box().y,
+            "
+Interval {
+    min: -0.5,
+    max: 2.5,
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_scatter_keeps_time() {
+        let rec = &mut empty_record();
+        rec.normal = Vec3::new(0., 1., 0.);
+        let r_in = Ray::new_with_time(Point3::new(0., 1., 0.), Vec3::new(1., -1., 0.), 0.25);
+
+        let materials: Vec<Arc<dyn Material>> = vec![
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+            Arc::new(Metal::new(Color::new(0.5, 0.5, 0.5), 0.)),
+            Arc::new(Dielectric::new(1.5)),
+        ];
+        for mat in materials {
+            let mut attenuation = Color::default();
+            let mut scattered = Ray::default();
+            mat.scatter(&r_in, rec, &mut attenuation, &mut scattered);
+            k9::snapshot!(scattered.time, "0.25");
+        }
+    }
+
+    #[test]
+    fn test_shutter() {
+        let mut cam = Camera::default();
+        cam.shutter_open = 0.25;
+        cam.shutter_close = 0.5;
+        cam.initialize();
+        for _ in 0..1000 {
+            let time = cam.get_ray(50, 50).time;
+            assert!((0.25..=0.5).contains(&time), "{}", time);
+        }
+
+        //a moving sphere's box only covers times 0 to 1, later rays would miss it in a BVH
+        cam.shutter_close = 3.;
+        k9::snapshot!(
+            cam.check_shutter().err().unwrap().to_string(),
+            "shutter interval 0.25 to 3 is not within 0 to 1"
+        );
+        cam.shutter_open = 0.75;
+        cam.shutter_close = 0.5;
+        k9::snapshot!(cam.check_shutter().is_err(), "true");
+        k9::snapshot!(
+            SceneDesc::from_toml("[camera]\nshutter_open = -1")
+                .err()
+                .unwrap()
+                .to_string(),
+            "shutter interval -1 to 1 is not within 0 to 1"
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_texture {
+    use super::test_util::*;
+    use super::*;
+
+    #[test]
+    fn test_sphere_uv() {
+        assert_eq!(Sphere::get_sphere_uv(&Point3::new(1., 0., 0.)), (0.5, 0.5));
+        assert_eq!(Sphere::get_sphere_uv(&Point3::new(0., 1., 0.)), (0.5, 1.0));
+        assert_eq!(Sphere::get_sphere_uv(&Point3::new(0., 0., 1.)), (0.25, 0.5));
+        assert_eq!(
+            Sphere::get_sphere_uv(&Point3::new(0., 0., -1.)),
+            (0.75, 0.5)
+        );
+    }
+
+    #[test]
+    fn test_sphere_hit_sets_uv() {
+        let material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let sphere = Sphere::new(Point3::new(0., 0., -2.), 0.5, material);
+        let rec = &mut empty_record();
+        let r = Ray::new(Point3::default(), Vec3::new(0., 0., -1.));
+        sphere.hit(&r, Interval::new_with_init(0.001, INFINITY), rec);
+        assert_eq!((rec.u, rec.v), (0.25, 0.5));
+    }
+
+    #[test]
+    fn test_checker() {
+        let checker =
+            CheckerTexture::new_with_colors(1., Color::new(0., 0., 0.), Color::new(1., 1., 1.));
+        k9::snapshot!(
+            checker.value(0., 0., &Point3::new(0.5, 0.5, 0.5)),
+            "(0, 0, 0)"
+        );
+        k9::snapshot!(
+            checker.value(0., 0., &Point3::new(1.5, 0.5, 0.5)),
+            "(1, 1, 1)"
+        );
+        k9::snapshot!(
+            checker.value(0., 0., &Point3::new(-0.5, 0.5, 0.5)),
+            "(1, 1, 1)"
+        );
+        k9::snapshot!(
+            checker.value(0., 0., &Point3::new(1.5, 1.5, 0.5)),
+            "(0, 0, 0)"
+        );
+    }
+
+    #[test]
+    fn test_ppm_round_trip() {
+        let img = Image::new_with_init(2, 3, |row, col| Pixel {
+            r: row as u8,
+            g: col as u8,
+            b: 20,
+        });
+        let loaded = Image::from_ppm(format!("{}", PPM(&img)).as_bytes()).unwrap();
+        k9::snapshot!(
+            PPM(&loaded),
+            "
+P3
+3 2
+255
+  0   0  20
+  0   1  20
+  0   2  20
+  1   0  20
+  1   1  20
+  1   2  20
+
+"
+        );
+    }
+
+    #[test]
+    fn test_binary_ppm() {
+        let mut bytes = b"P6\n# comment\n2 1\n255\n".to_vec();
+        bytes.extend([255, 0, 0, 0, 0, 255]);
+        let img = Image::from_ppm(&bytes).unwrap();
+        k9::snapshot!(PPM(&img.pixels[0][0]), "255   0   0");
+        k9::snapshot!(PPM(&img.pixels[0][1]), "  0   0 255");
+
+        k9::snapshot!(Image::from_ppm(b"P6\n2 1\n255\n\x00").is_err(), "true");
+        k9::snapshot!(Image::from_ppm(b"P5\n2 1\n255\n").is_err(), "true");
+
+        //a crafted header must neither overflow the raster size nor allocate for it
+        let huge = format!("P6\n{} {}\n255\n", usize::MAX, 2);
+        k9::snapshot!(
+            Image::from_ppm(huge.as_bytes()).err().unwrap().to_string(),
+            "PPM dimensions too large"
+        );
+        for magic in ["P3", "P6"] {
+            let large = format!("{}\n100000 100000 255\n0 0 0", magic);
+            k9::snapshot!(
+                Image::from_ppm(large.as_bytes()).err().unwrap().to_string(),
+                "PPM raster is truncated"
+            );
+        }
+    }
+
+    #[test]
+    fn test_image_texture() {
+        //left column red, right column blue
+        let img = Image::new_with_init(2, 2, |row, col| Pixel {
+            r: if col == 0 { 255 } else { 0 },
+            g: 0,
+            b: if col == 1 { 255 } else { 0 },
+        });
+        let tex = ImageTexture::new(img);
+        let p = Point3::default();
+        k9::snapshot!(tex.value(0.1, 0.5, &p), "(1, 0, 0)");
+        k9::snapshot!(tex.value(0.9, 0.5, &p), "(0, 0, 1)");
+        k9::snapshot!(tex.value(2., -1., &p), "(0, 0, 1)");
+
+        let empty = ImageTexture::new(Image::new(0, 0));
+        k9::snapshot!(empty.value(0.5, 0.5, &p), "(0, 1, 1)");
+    }
+
+    #[test]
+    fn test_lambertian_texture() {
+        let checker = CheckerTexture::new_with_colors(
+            1.,
+            Color::new(0.2, 0.2, 0.2),
+            Color::new(0.8, 0.8, 0.8),
+        );
+        let mat = Lambertian::new_with_texture(Arc::new(checker));
+        let rec = &mut empty_record();
+        rec.normal = Vec3::new(0., 1., 0.);
+        rec.p = Point3::new(1.5, 0., 0.);
+        let mut attenuation = Color::default();
+        let mut scattered = Ray::default();
+        mat.scatter(&Ray::default(), rec, &mut attenuation, &mut scattered);
+        k9::snapshot!(attenuation, "(0.8, 0.8, 0.8)");
+    }
+}
+
+#[cfg(test)]
+mod test_perlin {
+    use super::*;
+
+    fn sample_points() -> Vec<Point3> {
+        (0..500)
+            .map(|i| {
+                let i = i as f64;
+                Point3::new(i * 0.173, i * -0.291, i * 0.057)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_seed_is_reproducible() {
+        let a = Perlin::new(7);
+        let b = Perlin::new(7);
+        let c = Perlin::new(8);
+        let points = sample_points();
+
+        for p in &points {
+            assert_eq!(a.noise(p), b.noise(p));
+        }
+        assert!(points.iter().any(|p| a.noise(p) != c.noise(p)));
+    }
+
+    #[test]
+    fn test_noise_range() {
+        let perlin = Perlin::new(1);
+        for p in sample_points() {
+            let n = perlin.noise(&p);
+            assert!((-1. ..=1.).contains(&n));
+            assert!(perlin.turb(&p, 7) >= 0.);
+        }
+        //gradient noise vanishes on the lattice
+        k9::snapshot!(perlin.noise(&Point3::new(3., -2., 5.)), "0.0");
+    }
+
+    #[test]
+    fn test_noise_texture() {
+        let patterns = [
+            NoisePattern::Noise,
+            NoisePattern::Turbulence,
+            NoisePattern::Marble,
+            NoisePattern::Wood,
+        ];
+        let dark = Color::new(0.2, 0.1, 0.);
+        let light = Color::new(0.9, 0.7, 0.4);
+
+        for pattern in patterns {
+            let tex = NoiseTexture::new_with_colors(pattern, 4., 42, dark.clone(), light.clone());
+            let same = NoiseTexture::new_with_colors(pattern, 4., 42, dark.clone(), light.clone());
+            for p in sample_points() {
+                let c = tex.value(0., 0., &p);
+                assert!(dark.x <= c.x && c.x <= light.x);
+                assert!(dark.z <= c.z && c.z <= light.z);
+                k9::assert_equal!(format!("{:?}", c), format!("{:?}", same.value(0., 0., &p)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_light {
+    use super::*;
+
+    fn lit_scene() -> HittableList {
+        let mut world = HittableList::new();
+        let light = Arc::new(DiffuseLight::new(Color::new(4., 3., 2.)));
+        world.add(Arc::new(Sphere::new(Point3::new(0., 0., -3.), 1., light)));
+        world
+    }
+
+    #[test]
+    fn test_emitted() {
+        let light = DiffuseLight::new(Color::new(4., 3., 2.));
+        k9::snapshot!(light.emitted(0., 0., &Point3::default()), "(4, 3, 2)");
+
+        let diffuse = Lambertian::new(Color::new(0.5, 0.5, 0.5));
+        k9::snapshot!(diffuse.emitted(0., 0., &Point3::default()), "(0, 0, 0)");
+    }
+
+    #[test]
+    fn test_background() {
+        let mut cam = Camera::default();
+        let world = lit_scene();
+        let toward_light = Ray::new(Point3::default(), Vec3::new(0., 0., -1.));
+        let away = Ray::new(Point3::default(), Vec3::new(0., 0., 1.));
+
+        k9::snapshot!(
+            cam.ray_color(&toward_light, 10, &world, &HittableList::new()),
+            "(4, 3, 2)"
+        );
+        k9::snapshot!(
+            cam.ray_color(&away, 10, &world, &HittableList::new()),
+            "(0.75, 0.85, 1)"
+        );
+
+        cam.background = Some(Color::new(0., 0., 0.));
+        k9::snapshot!(
+            cam.ray_color(&toward_light, 10, &world, &HittableList::new()),
+            "(4, 3, 2)"
+        );
+        k9::snapshot!(
+            cam.ray_color(&away, 10, &world, &HittableList::new()),
+            "(0, 0, 0)"
+        );
+    }
+
+    #[test]
+    fn test_lit_by_emitter_only() {
+        //a diffuse floor under a light in a black void can only be lit by the light
+        let mut world = lit_scene();
+        let floor = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        world.add(Arc::new(Sphere::new(
+            Point3::new(0., -101., -3.),
+            100.,
+            floor,
+        )));
+
+        let mut cam = Camera::default();
+        cam.background = Some(Color::new(0., 0., 0.));
+        let down = Ray::new(Point3::new(0., 0., 0.), Vec3::new(0., -1., -1.));
+        let total: Color = (0..200)
+            .map(|_| cam.ray_color(&down, 10, &world, &HittableList::new()))
+            .sum();
+
+        assert!(total.x > 0.);
+        assert!(total.x > total.y && total.y > total.z);
+    }
+
+    #[test]
+    fn test_light_pdfs() {
+        let light: Arc<dyn Material> = Arc::new(DiffuseLight::new(Color::new(1., 1., 1.)));
+        let quad: Arc<dyn Hittable> = Arc::new(Quad::new(
+            Point3::new(-1., 1., -1.),
+            Vec3::new(2., 0., 0.),
+            Vec3::new(0., 0., 2.),
+            light.clone(),
+        ));
+        let shapes: Vec<(&str, Arc<dyn Hittable>)> = vec![
+            ("quad", quad.clone()),
+            (
+                "triangle",
+                Arc::new(Triangle::new(
+                    Point3::new(-1., 1., -1.),
+                    Point3::new(2., 1., 0.),
+                    Point3::new(0., 1., 2.),
+                    light.clone(),
+                )),
+            ),
+            (
+                "disk",
+                Arc::new(Disk::new(
+                    Point3::new(0., 0., -1.),
+                    Vec3::new(1., 0., 1.),
+                    1.,
+                    light.clone(),
+                )),
+            ),
+            (
+                "sphere",
+                Arc::new(Sphere::new(Point3::new(0., 2., 0.), 1., light.clone())),
+            ),
+            (
+                "inside sphere",
+                Arc::new(Sphere::new(Point3::new(0., 0.5, 0.), 2., light.clone())),
+            ),
+            (
+                "box",
+                Arc::new(quad_box(
+                    &Point3::new(1., -1., -1.),
+                    &Point3::new(2., 1., 1.),
+                    light.clone(),
+                )),
+            ),
+            (
+                "scaled quad",
+                Arc::new(
+                    Transformed::new(
+                        quad.clone(),
+                        Mat4::rotation_x(30.) * Mat4::scaling(&Vec3::new(0.5, 1., 2.)),
+                    )
+                    .unwrap(),
+                ),
+            ),
+            ("list", {
+                let mut list = HittableList::new_with_init(quad);
+                list.add(Arc::new(Sphere::new(Point3::new(0., -2., 0.), 1., light)));
+                Arc::new(list)
+            }),
+        ];
+
+        let origin = Point3::default();
+        for (name, shape) in shapes {
+            //every sampled direction reaches the shape
+            for _ in 0..100 {
+                let direction = shape.random(&origin);
+                assert!(shape.pdf_value(&origin, &direction) > 0., "{}", name);
+            }
+
+            //the density integrates to one over the sphere of directions
+            let n = 100_000;
+            let integral = (0..n)
+                .map(|_| shape.pdf_value(&origin, &Vec3::random_unit_vector()))
+                .sum::<f64>()
+                * 4.
+                * PI
+                / n as f64;
+            assert!((integral - 1.).abs() < 0.05, "{}: {}", name, integral);
+        }
+    }
+
+    //mean and standard error of n estimates of the light leaving the floor under the lamps,
+    //partly blocked by a sphere
+    fn floor_estimate(
+        floor: Arc<dyn Material>,
+        lamps: &HittableList,
+        sample_lamps: bool,
+        n: usize,
+        seed: u64,
+    ) -> (f64, f64) {
+        let mut world = HittableList::new();
+        world.add(Arc::new(Quad::new(
+            Point3::new(-10., 0., -10.),
+            Vec3::new(20., 0., 0.),
+            Vec3::new(0., 0., 20.),
+            floor,
+        )));
+        let blocker = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        world.add(Arc::new(Sphere::new(
+            Point3::new(0.3, 0.5, 0.),
+            0.2,
+            blocker,
+        )));
+        for lamp in &lamps.objects {
+            world.add(lamp.clone());
+        }
+        let lights = if sample_lamps {
+            lamps.clone()
+        } else {
+            HittableList::new()
+        };
+
+        let mut cam = Camera::default();
+        cam.background = Some(Color::new(0., 0., 0.));
+        let down = Ray::new(Point3::new(0., 1., 1.), Vec3::new(0., -1., -1.));
+        //fixed seeds keep the error comparisons from failing on an unlucky run
+        seed_random(seed);
+        let samples: Vec<f64> = (0..n)
+            .map(|_| cam.ray_color(&down, 5, &world, &lights).x)
+            .collect();
+
+        let mean = samples.iter().sum::<f64>() / n as f64;
+        let variance = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1) as f64;
+        (mean, (variance / n as f64).sqrt())
+    }
+
+    #[test]
+    fn test_next_event_estimation() {
+        //small bright lamps are what a random walk rarely finds
+        let lamp = Arc::new(DiffuseLight::new(Color::new(25., 25., 25.)));
+        let mut lamps = HittableList::new();
+        lamps.add(Arc::new(Quad::new(
+            Point3::new(-0.1, 1., -0.1),
+            Vec3::new(0.2, 0., 0.),
+            Vec3::new(0., 0., 0.2),
+            lamp.clone(),
+        )));
+        lamps.add(Arc::new(Sphere::new(Point3::new(1., 0.6, 0.), 0.1, lamp)));
+
+        let floor = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let (brute_force, brute_force_error) =
+            floor_estimate(floor.clone(), &lamps, false, 40_000, 1);
+        let (sampled, sampled_error) = floor_estimate(floor, &lamps, true, 40_000, 1);
+
+        //both converge to the same value
+        let error = (brute_force_error.powi(2) + sampled_error.powi(2)).sqrt();
+        assert!(
+            (brute_force - sampled).abs() < 4. * error,
+            "{} vs {} (+-{})",
+            brute_force,
+            sampled,
+            error
+        );
+        //and gets there with far less noise
+        assert!(sampled_error < brute_force_error / 5.);
+    }
+
+    #[test]
+    fn test_multiple_importance_sampling() {
+        //a glossy floor reflecting a large lamp, where light samples alone are mostly wasted,
+        //and a tiny bright one inside its lobe, where scattered rays alone rarely find it
+        let mut lamps = HittableList::new();
+        lamps.add(Arc::new(Quad::new(
+            Point3::new(-2., 1.5, -3.),
+            Vec3::new(4., 0., 0.),
+            Vec3::new(0., 0., 1.5),
+            Arc::new(DiffuseLight::new(Color::new(2., 2., 2.))),
+        )));
+        lamps.add(Arc::new(Sphere::new(
+            Point3::new(0., 0.8, -0.8),
+            0.02,
+            Arc::new(DiffuseLight::new(Color::new(600., 600., 600.))),
+        )));
+
+        for fuzz in [0.05, 0.3, 1.] {
+            let floor = Arc::new(Metal::new(Color::new(0.8, 0.8, 0.8), fuzz));
+            for seed in 1..=4 {
+                let (brute_force, brute_force_error) =
+                    floor_estimate(floor.clone(), &lamps, false, 20_000, seed);
+                let (mis, mis_error) = floor_estimate(floor.clone(), &lamps, true, 20_000, seed);
+
+                let error = (brute_force_error.powi(2) + mis_error.powi(2)).sqrt();
+                assert!(
+                    (brute_force - mis).abs() < 4. * error,
+                    "fuzz {} seed {}: {} vs {} (+-{})",
+                    fuzz,
+                    seed,
+                    brute_force,
+                    mis,
+                    error
+                );
+                //even the narrowest lobe is several times wider than the small lamp
+                assert!(
+                    mis_error < 0.5 * brute_force_error,
+                    "fuzz {} seed {}: {} vs {}",
+                    fuzz,
+                    seed,
+                    mis_error,
+                    brute_force_error
+                );
+            }
+        }
+    }
+
+    //mean and standard error of n paths from inside a closed diffuse room around a lamp,
+    //where nearly every path bounces many times before it finds the lamp
+    fn room_estimate(cam: &Camera, n: usize) -> (f64, f64, PathStats) {
+        let mut world = HittableList::new();
+        let walls = Arc::new(Lambertian::new(Color::new(0.7, 0.7, 0.7)));
+        world.add(Arc::new(Sphere::new(Point3::new(0., 0., 0.), 10., walls)));
+        let lamp = Arc::new(DiffuseLight::new(Color::new(1., 1., 1.)));
+        world.add(Arc::new(Sphere::new(Point3::new(0., 5., 0.), 1., lamp)));
+
+        let r = Ray::new(Point3::new(0., 0., 0.), Vec3::new(0., -1., 0.));
+        let mut stats = PathStats::default();
+        let samples: Vec<f64> = (0..n)
+            .map(|_| {
+                cam.ray_color_with_stats(
+                    &r,
+                    cam.max_depth,
+                    &world,
+                    &HittableList::new(),
+                    &mut stats,
+                )
+                .x
+            })
+            .collect();
+
+        let mean = samples.iter().sum::<f64>() / n as f64;
+        let variance = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1) as f64;
+        (mean, (variance / n as f64).sqrt(), stats)
+    }
+
+    #[test]
+    fn test_russian_roulette() {
+        //without roulette a deep cap is as good as unbiased, 0.7^60 of the light is lost
+        let mut cam = Camera::default();
+        cam.max_depth = 60;
+        cam.roulette_depth = 60;
+        let (reference, reference_error, reference_stats) = room_estimate(&cam, 20_000);
+        k9::snapshot!(reference_stats.roulette_terminated, "0");
+
+        cam.roulette_depth = 2;
+        let (roulette, roulette_error, stats) = room_estimate(&cam, 20_000);
+        let error = (reference_error.powi(2) + roulette_error.powi(2)).sqrt();
+        assert!(
+            (reference - roulette).abs() < 4. * error,
+            "{} vs {} (+-{})",
+            reference,
+            roulette,
+            error
+        );
+        assert!(stats.roulette_terminated > 10_000);
+        assert!(stats.average_length() < reference_stats.average_length() / 2.);
+
+        //a shallow cap alone darkens the room
+        cam.max_depth = 3;
+        cam.roulette_depth = 3;
+        let (capped, capped_error, _) = room_estimate(&cam, 20_000);
+        assert!(capped + 4. * capped_error < reference - 4. * reference_error);
+    }
+
+    #[test]
+    fn test_path_stats() {
+        let mut cam = Camera::default();
+        cam.max_depth = 3;
+        cam.roulette_depth = 3;
+        let (_, _, stats) = room_estimate(&cam, 1000);
+        k9::snapshot!(stats.paths, "1000");
+        //the walls never absorb, so every path either finds the lamp or reaches the cap
+        assert!(stats.depth_capped > 500);
+        assert_eq!(stats.roulette_terminated, 0);
+        assert!(stats.average_length() > 2. && stats.average_length() <= 3.);
+
+        //the cap still holds when roulette lets a path survive
+        cam.roulette_depth = 0;
+        let (_, _, stats) = room_estimate(&cam, 1000);
+        assert!(stats.average_length() <= 3.);
+        k9::snapshot!(PathStats::default().average_length(), "0.0");
+    }
+}
+
+#[cfg(test)]
+mod test_integrator {
+    use super::*;
+
+    //every kind of bounce: lamp sampling on the floor, glossy metal, glass and a fog ball,
+    //inside a closed room so paths only end by roulette or at the lamp
+    fn mixed_scene() -> (HittableList, HittableList) {
+        let mut world = HittableList::new();
+        world.add(Arc::new(Sphere::new(
+            Point3::new(0., 0., 0.),
+            20.,
+            Arc::new(Lambertian::new(Color::new(0.6, 0.5, 0.4))),
+        )));
+        world.add(Arc::new(Quad::new(
+            Point3::new(-10., 0., -10.),
+            Vec3::new(20., 0., 0.),
+            Vec3::new(0., 0., 20.),
+            Arc::new(Lambertian::new(Color::new(0.7, 0.7, 0.7))),
+        )));
+        world.add(Arc::new(Sphere::new(
+            Point3::new(-1.2, 0.5, -2.),
+            0.5,
+            Arc::new(Metal::new(Color::new(0.9, 0.8, 0.7), 0.3)),
+        )));
+        world.add(Arc::new(Sphere::new(
+            Point3::new(0., 0.5, -2.),
+            0.5,
+            Arc::new(Dielectric::new(1.5)),
+        )));
+        world.add(Arc::new(ConstantMedium::new(
+            Arc::new(Sphere::new(
+                Point3::new(1.2, 0.5, -2.),
+                0.5,
+                Arc::new(Lambertian::new(Color::default())),
+            )),
+            2.,
+            Color::new(0.8, 0.8, 0.8),
+        )));
+
+        let mut lights = HittableList::new();
+        lights.add(Arc::new(Quad::new(
+            Point3::new(-0.5, 3., -2.5),
+            Vec3::new(1., 0., 0.),
+            Vec3::new(0., 0., 1.),
+            Arc::new(DiffuseLight::new(Color::new(10., 10., 10.))),
+        )));
+        world.add(lights.objects[0].clone());
+        (world, lights)
+    }
+
+    fn mixed_camera() -> Camera {
+        let mut cam = Camera::default();
+        cam.image_width = 32;
+        cam.aspect_ratio = 2.;
+        cam.max_depth = 50;
+        cam.roulette_depth = 1;
+        cam.lookfrom = Point3::new(0., 1., 2.);
+        cam.lookat = Point3::new(0., 0.5, -2.);
+        cam.vfov = 60.;
+        cam.initialize();
+        cam
+    }
+
+    #[test]
+    fn test_loop_matches_recursion() {
+        let (world, lights) = mixed_scene();
+        let cam = mixed_camera();
+
+        let mut stats = PathStats::default();
+        let mut recursive_stats = PathStats::default();
+        for seed in 0..2000 {
+            let (col, row) = (seed as usize % 32, seed as usize / 32 % 16);
+            seed_random(seed);
+            let r = cam.get_ray(col, row);
+            let looped = cam.ray_color_with_stats(&r, cam.max_depth, &world, &lights, &mut stats);
+            seed_random(seed);
+            let r = cam.get_ray(col, row);
+            let recursive =
+                cam.ray_color_recursive(&r, cam.max_depth, &world, &lights, &mut recursive_stats);
+
+            let bits = |color: Color| <[f64; 3]>::from(color).map(f64::to_bits);
+            assert_eq!(bits(looped), bits(recursive), "seed {}", seed);
+        }
+        assert_eq!(stats, recursive_stats);
+        //long enough paths to go through roulette and several scatters
+        assert!(stats.average_length() > 2.5);
+        assert!(stats.roulette_terminated > 1000);
+    }
+
+    #[test]
+    fn test_long_paths_match_recursion() {
+        //a small lamp inside a white room, the walls absorb nothing so roulette never ends a
+        //path and nearly every path runs into max_depth, well past what ray_color keeps on
+        //the stack
+        let mut world = HittableList::new();
+        let walls = Arc::new(Lambertian::new(Color::new(1., 1., 1.)));
+        world.add(Arc::new(Sphere::new(Point3::new(0., 0., 0.), 10., walls)));
+        let lamp = Arc::new(Sphere::new(
+            Point3::new(0., 5., 0.),
+            0.1,
+            Arc::new(DiffuseLight::new(Color::new(1., 1., 1.))),
+        ));
+        world.add(lamp.clone());
+        let lights = HittableList::new_with_init(lamp);
+        let mut cam = mixed_camera();
+        cam.max_depth = 100;
+
+        let mut stats = PathStats::default();
+        let mut recursive_stats = PathStats::default();
+        for seed in 0..20 {
+            let r = Ray::new(Point3::new(0., 0., 0.), Vec3::new(1., -0.3, 0.2));
+            seed_random(seed);
+            let looped = cam.ray_color_with_stats(&r, cam.max_depth, &world, &lights, &mut stats);
+            seed_random(seed);
+            let recursive =
+                cam.ray_color_recursive(&r, cam.max_depth, &world, &lights, &mut recursive_stats);
+
+            let bits = |color: Color| <[f64; 3]>::from(color).map(f64::to_bits);
+            assert_eq!(bits(looped), bits(recursive), "seed {}", seed);
+        }
+        assert_eq!(stats, recursive_stats);
+        assert!(stats.depth_capped > 15, "{:?}", stats);
+    }
+
+    #[test]
+    fn test_seed_random() {
+        seed_random(7);
+        let first: Vec<f64> = (0..5).map(|_| random_f64()).collect();
+        seed_random(7);
+        let second: Vec<f64> = (0..5).map(|_| random_f64()).collect();
+        k9::snapshot!(first == second, "true");
+        seed_random(8);
+        k9::snapshot!(random_f64() == first[0], "false");
+    }
+
+    //cargo test --release bench_ray_color -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_ray_color() {
+        let (world, lights) = mixed_scene();
+        //wrapped in a list like the viewer's world
+        let world = HittableList::new_with_init(Arc::new(FlatBvh::new(&world, SplitMethod::Sah)));
+        let cam = mixed_camera();
+        let n = 1_000_000;
+        let time = |trace: &dyn Fn(&Ray) -> Color| {
+            seed_random(1);
+            let time_start = Instant::now();
+            let total: Color = (0..n)
+                .map(|i| trace(&cam.get_ray(i % 32, i / 32 % 16)))
+                .sum();
+            (time_start.elapsed(), total)
+        };
+
+        let (recursive, _) = time(&|r| {
+            cam.ray_color_recursive(r, cam.max_depth, &world, &lights, &mut PathStats::default())
+        });
+        let (looped, _) = time(&|r| cam.ray_color(r, cam.max_depth, &world, &lights));
+        println!(
+            "{} paths: recursive {:.2?}, loop {:.2?} ({:.2}x)",
+            n,
+            recursive,
+            looped,
+            recursive.as_secs_f64() / looped.as_secs_f64()
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_onb {
+    use super::test_util::*;
+    use super::*;
+
+    #[test]
+    fn test_basis() {
+        for w in [
+            Vec3::new(0., 1., 0.),
+            Vec3::new(1., 0., 0.),
+            Vec3::new(-3., 0.2, 0.5),
+            Vec3::new(1., 2., -3.),
+        ] {
+            let uvw = Onb::new(&w);
+            assert_near(&uvw.w, &w.unit());
+            for axis in [&uvw.u, &uvw.v, &uvw.w] {
+                assert!((axis.length() - 1.).abs() < 1e-12);
+            }
+            assert!(uvw.u.dot(&uvw.v).abs() < 1e-12);
+            assert!(uvw.u.dot(&uvw.w).abs() < 1e-12);
+            assert!(uvw.v.dot(&uvw.w).abs() < 1e-12);
+            //right-handed
+            assert_near(&uvw.u.cross(&uvw.v), &uvw.w);
+
+            let direction = Vec3::new(0.3, -2., 1.5);
+            assert_near(&uvw.transform(&uvw.to_local(&direction)), &direction);
+            assert_near(&uvw.transform(&Vec3::new(0., 0., 1.)), &uvw.w);
+        }
+    }
+
+    //samples directions and checks the pdf returned with each one matches pdf, that
+    //cos(theta) to w falls into each tenth of [lowest, 1] as often as pdf predicts and that
+    //the azimuth around w is uniform
+    fn check_distribution(
+        name: &str,
+        uvw: &Onb,
+        lowest: f64,
+        sample: impl Fn() -> (Vec3, f64),
+        pdf: impl Fn(&Vec3) -> f64,
+    ) {
+        let n = 100_000;
+        let bins = 10;
+        let mut counts = vec![0; bins];
+        let mut mean = Vec3::default();
+        for _ in 0..n {
+            let (direction, density) = sample();
+            assert!((direction.length() - 1.).abs() < 1e-9, "{}", name);
+            assert!(
+                (density - pdf(&direction)).abs() <= 1e-9 * density,
+                "{}",
+                name
+            );
+
+            let cos_theta = uvw.w.dot(&direction);
+            assert!(cos_theta > lowest - 1e-12, "{}", name);
+            let bin = ((cos_theta - lowest) / (1. - lowest) * bins as f64).max(0.) as usize;
+            counts[bin.min(bins - 1)] += 1;
+            mean = mean + direction;
+        }
+
+        //the density only depends on cos(theta), a ring of directions at cos(theta) = z
+        //covers 2 pi dz of solid angle
+        let steps = 1000;
+        let dz = (1. - lowest) / (bins * steps) as f64;
+        for (bin, count) in counts.iter().enumerate() {
+            let probability: f64 = (0..steps)
+                .map(|step| {
+                    let z = lowest + (bin * steps + step) as f64 * dz + dz / 2.;
+                    let direction = uvw.transform(&Vec3::new((1. - z * z).sqrt(), 0., z));
+                    2. * PI * pdf(&direction) * dz
+                })
+                .sum();
+            let expected = probability * n as f64;
+            assert!(
+                (*count as f64 - expected).abs() < 5. * expected.sqrt() + 5.,
+                "{} bin {}: {} != {}",
+                name,
+                bin,
+                count,
+                expected
+            );
+        }
+
+        let local = uvw.to_local(&(mean / n as f64));
+        assert!(local.x.abs() < 0.01 && local.y.abs() < 0.01, "{}", name);
+    }
+
+    #[test]
+    fn test_distributions() {
+        let uvw = Onb::new(&Vec3::new(1., 2., -3.));
+
+        check_distribution(
+            "cosine",
+            &uvw,
+            0.,
+            || uvw.random_cosine(),
+            |direction| uvw.cosine_pdf(direction),
+        );
+        check_distribution(
+            "hemisphere",
+            &uvw,
+            0.,
+            || uvw.random_hemisphere(),
+            |direction| uvw.hemisphere_pdf(direction),
+        );
+        for cos_theta_max in [0.2, 0.8, 0.99] {
+            check_distribution(
+                "cone",
+                &uvw,
+                0.,
+                || uvw.random_cone(cos_theta_max),
+                |direction| uvw.cone_pdf(direction, cos_theta_max),
+            );
+        }
+        for alpha in [0.1, 0.4, 1.] {
+            check_distribution(
+                "ggx",
+                &uvw,
+                0.,
+                || uvw.random_ggx(alpha),
+                |half| uvw.ggx_pdf(half, alpha),
+            );
+            //seen straight down the density only depends on cos(theta), wide lobes send
+            //some directions below the surface
+            check_distribution(
+                "ggx reflection",
+                &uvw,
+                -1.,
+                || uvw.random_ggx_reflection(&uvw.w, alpha),
+                |direction| uvw.ggx_reflection_pdf(&uvw.w, direction, alpha),
+            );
+        }
+
+        //seen at a grazing angle each sample still comes with its density
+        let outgoing = uvw.transform(&Vec3::new(0.8, 0., 0.6));
+        for _ in 0..1000 {
+            let (direction, density) = uvw.random_ggx_reflection(&outgoing, 0.3);
+            let pdf = uvw.ggx_reflection_pdf(&outgoing, &direction, 0.3);
+            assert!((density - pdf).abs() <= 1e-9 * density);
+        }
+    }
+
+    #[test]
+    fn test_pdfs() {
+        let uvw = Onb::new(&Vec3::new(0., 0., 1.));
+        let up = Vec3::new(0., 0., 1.);
+        let down = Vec3::new(0., 0., -1.);
+        let side = Vec3::new(1., 0., 1.);
+
+        k9::snapshot!(uvw.cosine_pdf(&up) * PI, "1.0");
+        k9::snapshot!(uvw.cosine_pdf(&down), "0.0");
+        k9::snapshot!(uvw.hemisphere_pdf(&side) * 2. * PI, "1.0");
+        k9::snapshot!(uvw.hemisphere_pdf(&down), "0.0");
+        //45 degrees is inside a cone reaching cos = 0.7 but not one reaching cos = 0.8
+        assert!(uvw.cone_pdf(&side, 0.7) > 0.);
+        k9::snapshot!(uvw.cone_pdf(&side, 0.8), "0.0");
+        //a roughness of 1 makes GGX normals cosine distributed
+        assert!((uvw.ggx_pdf(&side, 1.) - uvw.cosine_pdf(&side)).abs() < 1e-12);
+        k9::snapshot!(uvw.ggx_pdf(&down, 0.5), "0.0");
+        //smoother surfaces concentrate normals around w
+        assert!(uvw.ggx_pdf(&up, 0.1) > uvw.ggx_pdf(&up, 0.5));
+        //a mirror is clamped to a very smooth surface instead of 0 / 0
+        assert!(uvw.ggx_pdf(&up, 0.).is_finite());
+        assert!(uvw.ggx_reflection_pdf(&up, &up, 0.) > uvw.ggx_reflection_pdf(&up, &up, 0.1));
+        k9::snapshot!(uvw.ggx_reflection_pdf(&up, &down, 0.5), "0.0");
+    }
+}
+
+#[cfg(test)]
+mod test_pdf {
+    use super::*;
+
+    //integral of the density over all directions, from uniformly spread directions
+    fn total(pdf: &dyn Pdf) -> f64 {
+        let n = 200_000;
+        (0..n)
+            .map(|_| pdf.value(&Vec3::random_unit_vector()))
+            .sum::<f64>()
+            * 4.
+            * PI
+            / n as f64
+    }
+
+    //solid angle of the directions the pdf can generate, from its own samples
+    fn support(pdf: &dyn Pdf) -> f64 {
+        let n = 100_000;
+        (0..n).map(|_| 1. / pdf.value(&pdf.generate())).sum::<f64>() / n as f64
+    }
+
+    fn assert_near(value: f64, expected: f64, name: &str) {
+        assert!(
+            (value / expected - 1.).abs() < 0.03,
+            "{}: {} != {}",
+            name,
+            value,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_power_heuristic() {
+        k9::snapshot!(power_heuristic(1., 0.), "1.0");
+        k9::snapshot!(power_heuristic(0., 1.), "0.0");
+        k9::snapshot!(power_heuristic(2., 2.), "0.5");
+        k9::snapshot!(power_heuristic(3., 1.), "0.9");
+        //the two weights of a direction always add up to one
+        k9::snapshot!(power_heuristic(0.2, 0.7) + power_heuristic(0.7, 0.2), "1.0");
+    }
+
+    #[test]
+    fn test_normalized() {
+        let normal = Vec3::new(0., 1., 0.);
+        let light = Sphere::new(
+            Point3::new(0., 1.5, 0.),
+            1.,
+            Arc::new(DiffuseLight::new(Color::new(1., 1., 1.))),
+        );
+        let cosine = CosinePdf::new(&normal);
+        let towards_light = HittablePdf::new(&light, &Point3::default());
+
+        assert_near(total(&cosine), 1., "cosine");
+        assert_near(total(&SpherePdf), 1., "sphere");
+        assert_near(total(&towards_light), 1., "hittable");
+        assert_near(total(&MixturePdf::new(&cosine, &SpherePdf)), 1., "mixture");
+        assert_near(
+            total(&MixturePdf::new_with_weight(&towards_light, &cosine, 0.8)),
+            1.,
+            "weighted mixture",
+        );
+    }
+
+    #[test]
+    fn test_support() {
+        let reflected = Vec3::new(1., 1., 0.);
+        k9::snapshot!(SpherePdf.value(&reflected) * 4. * PI, "1.0");
+        assert_near(support(&SpherePdf), 4. * PI, "sphere");
+        assert_near(
+            support(&MixturePdf::new(&CosinePdf::new(&reflected), &SpherePdf)),
+            4. * PI,
+            "mixture",
+        );
+
+        //a fuzz sphere below radius 1 covers a cone, at radius 1 the whole hemisphere
+        for fuzz in [0.1_f64, 0.5, 0.9] {
+            let cone = 2. * PI * (1. - (1. - fuzz * fuzz).sqrt());
+            assert_near(support(&FuzzPdf::new(&reflected, fuzz)), cone, "fuzz");
+        }
+        assert_near(support(&FuzzPdf::new(&reflected, 1.)), 2. * PI, "fuzz");
+    }
+
+    #[test]
+    fn test_fuzz() {
+        let reflected = Vec3::new(0., 0., 1.);
+        let lobe = FuzzPdf::new(&reflected, 0.5);
+        //nothing outside the cone or behind the mirror direction
+        k9::snapshot!(lobe.value(&Vec3::new(1., 0., 1.)), "0.0");
+        k9::snapshot!(lobe.value(&-&reflected), "0.0");
+        //directions grazing the sphere are the most likely ones
+        assert!(lobe.value(&reflected) < lobe.value(&Vec3::new(0.5, 0., 0.87)));
+        //even when rounding puts them just outside it
+        let sin_theta = (0.25_f64 + 1e-14).sqrt();
+        let grazing = Vec3::new(sin_theta, 0., (1. - sin_theta * sin_theta).sqrt());
+        let density = lobe.value(&grazing);
+        assert!(density > 0. && density.is_finite(), "{}", density);
+
+        //a fuzz of 1 turns the mirror direction into a cosine lobe around it
+        let widest = FuzzPdf::new(&reflected, 1.);
+        let cosine = CosinePdf::new(&reflected);
+        for _ in 0..100 {
+            let direction = Vec3::random_unit_vector();
+            assert!((widest.value(&direction) - cosine.value(&direction)).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_material_pdfs() {
+        //scatter samples exactly what scattering_pdf describes
+        let mut rec = test_util::empty_record();
+        rec.normal = Vec3::new(0., 1., 0.);
+        rec.front_face = true;
+        let r_in = Ray::new(Point3::new(-1., 1., 0.), Vec3::new(1., -1., 0.));
+        let materials: Vec<(&str, Arc<dyn Material>, f64)> = vec![
+            (
+                "lambertian",
+                Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+                2. * PI,
+            ),
+            (
+                "isotropic",
+                Arc::new(Isotropic::new(Color::new(0.5, 0.5, 0.5))),
+                4. * PI,
+            ),
+            (
+                "metal",
+                Arc::new(Metal::new(Color::new(0.5, 0.5, 0.5), 0.5)),
+                2. * PI * (1. - 0.75_f64.sqrt()),
+            ),
+        ];
+
+        for (name, material, support) in materials {
+            let n = 100_000;
+            let mut scattered = Ray::default();
+            let mut attenuation = Color::default();
+            let measured = (0..n)
+                .map(|_| {
+                    material.scatter(&r_in, &rec, &mut attenuation, &mut scattered);
+                    1. / material.scattering_pdf(&r_in, &rec, &scattered.direction)
+                })
+                .sum::<f64>()
+                / n as f64;
+            assert_near(measured, support, name);
+
+            //attenuation is bsdf over pdf
+            let direction = Vec3::new(0.8, 1., 0.1);
+            let ratio = material.bsdf(&r_in, &rec, &direction).x
+                / material.scattering_pdf(&r_in, &rec, &direction);
+            assert!((ratio - 0.5).abs() < 1e-12, "{}", name);
+        }
+        let mirror = Metal::new(Color::new(1., 1., 1.), 0.);
+        let brushed = Metal::new(Color::new(1., 1., 1.), 0.1);
+        k9::snapshot!(Dielectric::new(1.5).is_specular(), "true");
+        k9::snapshot!(mirror.is_specular(), "true");
+        k9::snapshot!(brushed.is_specular(), "false");
+    }
+}
+
+#[cfg(test)]
+mod test_planar {
+    use super::test_util::*;
+    use super::*;
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    fn down_at(x: f64, z: f64) -> Ray {
+        Ray::new(Point3::new(x, 5., z), Vec3::new(0., -1., 0.))
+    }
+
+    #[test]
+    fn test_quad() {
+        //unit square floor at y = 0, normal up
+        let quad = Quad::new(
+            Point3::new(0., 0., 0.),
+            Vec3::new(0., 0., 1.),
+            Vec3::new(1., 0., 0.),
+            material(),
+        );
+        let rec = &mut empty_record();
+
+        k9::snapshot!(quad.hit(&down_at(0.25, 0.75), ray_t(), rec), "true");
+        k9::snapshot!(&rec.p, "(0.25, 0, 0.75)");
+        k9::snapshot!(&rec.normal, "(0, 1, 0)");
+        k9::snapshot!(rec.front_face, "true");
+        assert_eq!((rec.u, rec.v), (0.75, 0.25));
+
+        k9::snapshot!(quad.hit(&down_at(1.5, 0.5), ray_t(), rec), "false");
+        k9::snapshot!(quad.hit(&down_at(-0.1, 0.5), ray_t(), rec), "false");
+
+        let up = Ray::new(Point3::new(0.5, -1., 0.5), Vec3::new(0., 1., 0.));
+        k9::snapshot!(quad.hit(&up, ray_t(), rec), "true");
+        k9::snapshot!(&rec.normal, "(-0, -1, -0)");
+        k9::snapshot!(rec.front_face, "false");
+
+        let parallel = Ray::new(Point3::new(0.5, 0., -1.), Vec3::new(0., 0., 1.));
+        k9::snapshot!(quad.hit(&parallel, ray_t(), rec), "false");
+
+        let bbox = quad.bounding_box();
+        assert!(bbox.y.size() > 0.);
+        assert_eq!(
+            (bbox.x.min, bbox.x.max, bbox.z.min, bbox.z.max),
+            (0.0, 1.0, 0.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn test_triangle() {
+        let tri = Triangle::new(
+            Point3::new(0., 0., 0.),
+            Point3::new(0., 0., 1.),
+            Point3::new(1., 0., 0.),
+            material(),
+        );
+        let rec = &mut empty_record();
+
+        k9::snapshot!(tri.hit(&down_at(0.25, 0.5), ray_t(), rec), "true");
+        k9::snapshot!(&rec.normal, "(0, 1, 0)");
+        assert_eq!((rec.u, rec.v), (0.5, 0.25));
+
+        //inside the bounding square, outside the triangle
+        k9::snapshot!(tri.hit(&down_at(0.75, 0.75), ray_t(), rec), "false");
+        k9::snapshot!(tri.hit(&down_at(-0.1, 0.5), ray_t(), rec), "false");
+    }
+
+    #[test]
+    fn test_disk() {
+        let disk = Disk::new(
+            Point3::new(0., 1., 0.),
+            Vec3::new(0., 2., 0.),
+            2.,
+            material(),
+        );
+        let rec = &mut empty_record();
+
+        k9::snapshot!(disk.hit(&down_at(1., 1.), ray_t(), rec), "true");
+        k9::snapshot!(&rec.p, "(1, 1, 1)");
+        k9::snapshot!(&rec.normal, "(0, 1, 0)");
+        assert!((rec.v - 2f64.sqrt() / 2.).abs() < 1e-12);
+        assert!((0. ..=1.).contains(&rec.u));
+
+        k9::snapshot!(disk.hit(&down_at(1.5, 1.5), ray_t(), rec), "false");
+
+        let bbox = disk.bounding_box();
+        assert_eq!(
+            (bbox.x.min, bbox.x.max, bbox.z.min, bbox.z.max),
+            (-2.0, 2.0, -2.0, 2.0)
+        );
+        assert!(bbox.y.size() > 0. && bbox.y.size() < 0.001);
+    }
+
+    #[test]
+    fn test_quad_box() {
+        let sides = quad_box(
+            &Point3::new(1., 1., 1.),
+            &Point3::new(-1., 0., -1.),
+            material(),
+        );
+        k9::snapshot!(sides.objects.len(), "6");
+
+        let bbox = sides.bounding_box();
+        assert!(bbox.y.min.abs() < 0.001 && (bbox.y.max - 1.).abs() < 0.001);
+
+        //every ray from inside leaves through a side whose normal faces the ray
+        for dir in [
+            Vec3::new(1., 0., 0.),
+            Vec3::new(-1., 0., 0.),
+            Vec3::new(0., 1., 0.),
+            Vec3::new(0., -1., 0.),
+            Vec3::new(0., 0., 1.),
+            Vec3::new(0., 0., -1.),
+        ] {
+            let rec = &mut empty_record();
+            let r = Ray::new(Point3::new(0.1, 0.4, -0.2), dir.clone());
+            k9::snapshot!(sides.hit(&r, ray_t(), rec), "true");
+            k9::snapshot!(rec.front_face, "false");
+            assert!(rec.normal.dot(&dir) < 0.);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_mesh {
+    use super::test_util::*;
+    use super::*;
+    use std::path::Path;
+
+    //unit square in the y = 0 plane split into two triangles, normals tilted outwards
+    const SQUARE_OBJ: &str = "
+# square
+v 0 0 0
+v 1 0 0
+v 1 0 1
+v 0 0 1
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn -1 1 -1
+vn 1 1 -1
+vn 1 1 1
+vn -1 1 1
+f 1/1/1 4/4/4 3/3/3 2/2/2
+";
+
+    fn down_at(x: f64, z: f64) -> Ray {
+        Ray::new(Point3::new(x, 5., z), Vec3::new(0., -1., 0.))
+    }
+
+    #[test]
+    fn test_parse_obj() {
+        let world = parse_obj(SQUARE_OBJ, Path::new(""), false, &mut Vec::new()).unwrap();
+        k9::snapshot!(world.objects.len(), "1");
+
+        let rec = &mut empty_record();
+        k9::snapshot!(world.hit(&down_at(0.25, 0.5), ray_t(), rec), "true");
+        k9::snapshot!(&rec.p, "(0.25, 0, 0.5)");
+        k9::snapshot!(&rec.normal, "(0, 1, 0)");
+        assert!((rec.u - 0.25).abs() < 1e-12 && (rec.v - 0.5).abs() < 1e-12);
+
+        k9::snapshot!(world.hit(&down_at(0.75, 0.9), ray_t(), rec), "true");
+        k9::snapshot!(world.hit(&down_at(1.25, 0.5), ray_t(), rec), "false");
+
+        let bbox = world.bounding_box();
+        assert_eq!(
+            (bbox.x.min, bbox.x.max, bbox.z.min, bbox.z.max),
+            (0., 1., 0., 1.)
+        );
+    }
+
+    #[test]
+    fn test_smooth_shading() {
+        let world = parse_obj(SQUARE_OBJ, Path::new(""), true, &mut Vec::new()).unwrap();
+        let rec = &mut empty_record();
+
+        world.hit(&down_at(0.9, 0.9), ray_t(), rec);
+        assert!(rec.normal.x > 0. && rec.normal.z > 0.);
+        assert!((rec.normal.length() - 1.).abs() < 1e-12);
+
+        //the shading normal flips with the face when hit from below
+        let up = Ray::new(Point3::new(0.1, -5., 0.1), Vec3::new(0., 1., 0.));
+        world.hit(&up, ray_t(), rec);
+        k9::snapshot!(rec.front_face, "false");
+        assert!(rec.normal.y < 0. && rec.normal.x > 0.);
+    }
+
+    #[test]
+    fn test_obj_errors() {
+        k9::snapshot!(
+            parse_obj("v 0 0 0\nf 1 2 3\n", Path::new(""), false, &mut Vec::new())
+                .err()
+                .unwrap()
+                .to_string(),
+            "obj line 2: bad face index"
+        );
+        k9::snapshot!(
+            parse_obj("v 0 zero 0\n", Path::new(""), false, &mut Vec::new())
+                .err()
+                .unwrap()
+                .to_string(),
+            "obj line 1: bad vertex"
+        );
+    }
+
+    #[test]
+    fn test_negative_indices_and_materials() {
+        let source = "
+v 0 0 0
+v 1 0 0
+v 0 0 1
+usemtl red
+f -3 -1 -2
+v 0 1 0
+v 1 1 0
+v 0 1 1
+usemtl blue
+f -3 -1 -2
+usemtl red
+f 1 3 2
+";
+        let world = parse_obj(source, Path::new(""), false, &mut Vec::new()).unwrap();
+        k9::snapshot!(world.objects.len(), "2");
+        let rec = &mut empty_record();
+        k9::snapshot!(world.hit(&down_at(0.2, 0.2), ray_t(), rec), "true");
+        k9::snapshot!(&rec.p, "(0.2, 1, 0.2)");
+    }
+
+    #[test]
+    fn test_parse_mtl() {
+        let source = "
+newmtl matte
+Kd 0.1 0.2 0.3
+illum 2
+
+newmtl mirror
+Kd 0 0 0
+Ks 0.9 0.9 0.9
+Ns 1000
+illum 3
+
+newmtl glass
+Ni 1.5
+d 0.2
+
+newmtl missing_texture
+Kd 0.4 0.5 0.6
+map_Kd no_such_texture.ppm
+";
+        let mut warnings = Vec::new();
+        let materials = parse_mtl(source, Path::new(""), &mut warnings).unwrap();
+        k9::snapshot!(materials.len(), "4");
+        //the missing texture is reported, not printed
+        assert_eq!(warnings.len(), 1);
+        assert!(
+            warnings[0].starts_with("no_such_texture.ppm: ")
+                && warnings[0].ends_with(", using Kd instead"),
+            "{}",
+            warnings[0]
+        );
+
+        let rec = &mut empty_record();
+        rec.normal = Vec3::new(0., 1., 0.);
+        let r_in = Ray::new(Point3::new(-1., 1., 0.), Vec3::new(1., -1., 0.));
+        let scatter = |name: &str| {
+            let mut attenuation = Color::default();
+            let mut scattered = Ray::default();
+            materials[name].scatter(&r_in, rec, &mut attenuation, &mut scattered);
+            attenuation
+        };
+
+        k9::snapshot!(scatter("matte"), "(0.1, 0.2, 0.3)");
+        k9::snapshot!(scatter("mirror"), "(0.9, 0.9, 0.9)");
+        k9::snapshot!(scatter("glass"), "(1, 1, 1)");
+        k9::snapshot!(scatter("missing_texture"), "(0.4, 0.5, 0.6)");
+    }
+
+    #[test]
+    fn test_load_obj_with_mtllib() {
+        let dir = std::env::temp_dir().join(format!("ray_tracer_obj_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("square.mtl"),
+            "newmtl shiny\nKs 1 1 1\nillum 3\nnewmtl unused\nmap_Kd gone.ppm\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("square.obj"),
+            format!("mtllib square.mtl\nusemtl shiny\n{}", SQUARE_OBJ),
+        )
+        .unwrap();
+
+        let mut warnings = Vec::new();
+        let world = load_obj(dir.join("square.obj"), false, &mut warnings).unwrap();
+        //textures are looked up next to the .mtl file, and a missing one reaches the caller
+        k9::snapshot!(warnings.len(), "1");
+        assert!(warnings[0].starts_with(&dir.join("gone.ppm").display().to_string()));
+        let rec = &mut empty_record();
+        k9::snapshot!(world.hit(&down_at(0.5, 0.5), ray_t(), rec), "true");
+
+        let mut attenuation = Color::default();
+        let mut scattered = Ray::default();
+        rec.mat
+            .scatter(&down_at(0.5, 0.5), rec, &mut attenuation, &mut scattered);
+        k9::snapshot!(scattered.direction, "(0, 1, 0)");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod test_transform {
+    use super::test_util::*;
+    use super::*;
+
+    fn unit_sphere() -> Arc<dyn Hittable> {
+        let material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        Arc::new(Sphere::new(Point3::default(), 1., material))
+    }
+
+    #[test]
+    fn test_mat4() {
+        let m = Mat4::translation(&Vec3::new(1., 2., 3.))
+            * Mat4::rotation_y(90.)
+            * Mat4::scaling(&Vec3::new(2., 2., 2.));
+
+        let p = m.transform_point(&Point3::new(1., 0., 0.));
+        assert_near(&p, &Point3::new(1., 2., 1.));
+
+        //directions ignore the translation
+        let v = m.transform_vector(&Vec3::new(0., 1., 0.));
+        assert_near(&v, &Vec3::new(0., 2., 0.));
+
+        let round_trip = m.inverse().unwrap() * m;
+        for i in 0..4 {
+            for j in 0..4 {
+                let expected = if i == j { 1. } else { 0. };
+                assert!((round_trip.m[i][j] - expected).abs() < 1e-12);
+            }
+        }
+
+        k9::snapshot!(Mat4::scaling(&Vec3::new(1., 0., 1.)).inverse(), "None");
+        k9::snapshot!(
+            Transformed::scale(unit_sphere(), &Vec3::new(1., 0., 1.)).is_none(),
+            "true"
+        );
+    }
+
+    #[test]
+    fn test_translate() {
+        let moved = Transformed::translate(unit_sphere(), &Vec3::new(0., 0., -5.));
+        let rec = &mut empty_record();
+        let r = Ray::new(Point3::default(), Vec3::new(0., 0., -1.));
+
+        k9::snapshot!(moved.hit(&r, ray_t(), rec), "true");
+        k9::snapshot!(rec.t, "4.0");
+        k9::snapshot!(&rec.p, "(0, 0, -4)");
+        k9::snapshot!(&rec.normal, "(0, 0, 1)");
+
+        let bbox = moved.bounding_box();
+        assert_eq!((bbox.z.min, bbox.z.max), (-6., -4.));
+    }
+
+    #[test]
+    fn test_scaled_normal() {
+        //ellipsoid stretched along x, the normal must stay perpendicular to the surface
+        let ellipsoid = Transformed::scale(unit_sphere(), &Vec3::new(4., 1., 1.)).unwrap();
+        let rec = &mut empty_record();
+        let p = Point3::new(2., 3f64.sqrt() / 2., 0.);
+        let r = Ray::new(Point3::new(2., 5., 0.), Vec3::new(0., -1., 0.));
+
+        k9::snapshot!(ellipsoid.hit(&r, ray_t(), rec), "true");
+        assert_near(&rec.p, &p);
+        let expected = Vec3::new(p.x / 16., p.y, 0.).unit();
+        assert_near(&rec.normal, &expected);
+    }
+
+    #[test]
+    fn test_rotated_instances() {
+        //one quad shared by two instances facing different ways
+        let material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let wall: Arc<dyn Hittable> = Arc::new(Quad::new(
+            Point3::new(-1., -1., 0.),
+            Vec3::new(2., 0., 0.),
+            Vec3::new(0., 2., 0.),
+            material,
+        ));
+        let facing_x = Transformed::new(
+            wall.clone(),
+            Mat4::translation(&Vec3::new(3., 0., 0.)) * Mat4::rotation_y(90.),
+        )
+        .unwrap();
+        let facing_z = Transformed::translate(wall, &Vec3::new(0., 0., 3.));
+
+        let rec = &mut empty_record();
+        let along_x = Ray::new(Point3::default(), Vec3::new(1., 0., 0.));
+        k9::snapshot!(facing_x.hit(&along_x, ray_t(), rec), "true");
+        assert_near(&rec.p, &Point3::new(3., 0., 0.));
+        assert_near(&rec.normal, &Vec3::new(-1., 0., 0.));
+        k9::snapshot!(rec.front_face, "false");
+        k9::snapshot!(facing_z.hit(&along_x, ray_t(), rec), "false");
+
+        let bbox = facing_x.bounding_box();
+        assert!(bbox.x.size() < 0.001);
+        assert!((bbox.z.size() - 2.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_transform_keeps_time() {
+        let material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let moving: Arc<dyn Hittable> = Arc::new(Sphere::new_moving(
+            Point3::default(),
+            Point3::new(0., 10., 0.),
+            1.,
+            material,
+        ));
+        let moved = Transformed::translate(moving, &Vec3::new(0., 0., -5.));
+        let r = Ray::new_with_time(Point3::default(), Vec3::new(0., 0., -1.), 1.);
+        k9::snapshot!(moved.hit(&r, ray_t(), &mut empty_record()), "false");
+    }
+}
+
+#[cfg(test)]
+mod test_medium {
+    use super::test_util::*;
+    use super::*;
+
+    fn fog(center: Point3, radius: f64, density: f64) -> ConstantMedium {
+        let glass = Arc::new(Dielectric::new(1.5));
+        let boundary = Arc::new(Sphere::new(center, radius, glass));
+        ConstantMedium::new(boundary, density, Color::new(0.9, 0.9, 0.9))
+    }
+
+    #[test]
+    fn test_dense_and_thin() {
+        let r = Ray::new(Point3::default(), Vec3::new(0., 0., -1.));
+
+        let dense = fog(Point3::new(0., 0., -5.), 1., 1e6);
+        let rec = &mut empty_record();
+        k9::snapshot!(dense.hit(&r, ray_t(), rec), "true");
+        assert!((rec.t - 4.).abs() < 1e-3);
+
+        let thin = fog(Point3::new(0., 0., -5.), 1., 1e-9);
+        k9::snapshot!(thin.hit(&r, ray_t(), &mut empty_record()), "false");
+
+        let away = Ray::new(Point3::default(), Vec3::new(0., 0., 1.));
+        k9::snapshot!(dense.hit(&away, ray_t(), &mut empty_record()), "false");
+    }
+
+    #[test]
+    fn test_mean_free_path_from_inside() {
+        //a ray starting inside a huge boundary scatters after 1 / density on average
+        let density = 2.;
+        for radius in [100., -100.] {
+            let medium = fog(Point3::default(), radius, density);
+            let samples = 5000;
+            let mut total = 0.;
+            for _ in 0..samples {
+                let r = Ray::new(Point3::default(), Vec3::random_unit_vector());
+                let rec = &mut empty_record();
+                assert!(medium.hit(&r, ray_t(), rec));
+                assert!(rec.t > 0.);
+                total += rec.t;
+            }
+            let mean = total / samples as f64;
+            assert!(
+                (mean - 1. / density).abs() < 0.05,
+                "mean free path {}",
+                mean
+            );
+        }
+    }
+
+    #[test]
+    fn test_hollow_boundary() {
+        //same medium inside a negative radius sphere, as used for hollow glass
+        let r = Ray::new(Point3::new(0., 0., 0.), Vec3::new(0., 0., -1.));
+        let hollow = fog(Point3::new(0., 0., -5.), -1., 1e6);
+        let rec = &mut empty_record();
+        k9::snapshot!(hollow.hit(&r, ray_t(), rec), "true");
+        assert!((rec.t - 4.).abs() < 1e-3);
+        k9::snapshot!(hollow.bounding_box().z.size(), "2.0");
+
+        //the scattering position never leaves the boundary
+        let thin = fog(Point3::new(0., 0., -5.), -1., 0.5);
+        for _ in 0..1000 {
+            let rec = &mut empty_record();
+            if thin.hit(&r, ray_t(), rec) {
+                assert!(rec.t >= 4. && rec.t <= 6.);
+            }
+        }
+    }
+
+    #[test]
+    fn test_isotropic() {
+        let iso = Isotropic::new(Color::new(0.3, 0.4, 0.5));
+        let rec = &mut empty_record();
+        let r_in = Ray::new_with_time(Point3::default(), Vec3::new(1., 0., 0.), 0.75);
+
+        let mut mean = Vec3::default();
+        for _ in 0..2000 {
+            let mut attenuation = Color::default();
+            let mut scattered = Ray::default();
+            k9::snapshot!(
+                iso.scatter(&r_in, rec, &mut attenuation, &mut scattered),
+                "true"
+            );
+            k9::snapshot!(attenuation, "(0.3, 0.4, 0.5)");
+            k9::snapshot!(scattered.time, "0.75");
+            mean = mean + scattered.direction / 2000.;
+        }
+        //no preferred direction
+        assert!(mean.length() < 0.1);
+    }
+}
+
+#[cfg(test)]
+mod test_scene {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_round_trip() {
+        let scene = default_scene();
+        let toml = scene.to_toml().unwrap();
+        k9::snapshot!(
+            SceneDesc::from_toml(&toml).unwrap().to_toml().unwrap() == toml,
+            "true"
+        );
+
+        let json = scene.to_json().unwrap();
+        k9::snapshot!(
+            SceneDesc::from_json(&json).unwrap().to_toml().unwrap() == toml,
+            "true"
+        );
+
+        //the example file is an export of the built-in scene
+        let file = SceneDesc::load("scenes/three_spheres.toml").unwrap();
+        k9::snapshot!(file.to_toml().unwrap() == toml, "true");
+        k9::snapshot!(file.camera.image_width, "200");
+        k9::snapshot!(
+            file.build_world(Path::new("scenes"), &mut Vec::new())
+                .unwrap()
+                .objects
+                .len(),
+            "5"
+        );
+    }
+
+    #[test]
+    fn test_textures_and_transforms() {
+        let source = r#"
+            [camera]
+            image_width = 64
+            background = [0.1, 0.1, 0.1]
+
+            [materials.floor]
+            type = "lambertian"
+            albedo = { type = "checker", scale = 0.5, even = [0, 0, 0], odd = { type = "noise", pattern = "marble", scale = 4 } }
+
+            [materials.lamp]
+            type = "diffuse_light"
+            emit = [4, 4, 4]
+
+            [[objects]]
+            type = "quad"
+            q = [-1, 0, -1]
+            u = [2, 0, 0]
+            v = [0, 0, 2]
+            material = "floor"
+
+            [[objects]]
+            type = "box"
+            a = [0, 0, 0]
+            b = [1, 1, 1]
+            material = "lamp"
+            transform = [{ translate = [-0.5, 0, -0.5] }, { rotate_y = 45 }, { translate = [0, 2, 0] }]
+
+            [[objects]]
+            type = "medium"
+            density = 0.5
+            albedo = [1, 1, 1]
+            boundary = { type = "sphere", center = [0, 0, 0], radius = -3, material = "floor" }
+        "#;
+        let scene = SceneDesc::from_toml(source).unwrap();
+        k9::snapshot!(scene.camera.image_width, "64");
+        k9::snapshot!(scene.camera.vfov, "90.0");
+        k9::snapshot!(scene.camera.background.as_ref().unwrap(), "(0.1, 0.1, 0.1)");
+
+        let (world, lights) = scene.build(Path::new(""), &mut Vec::new()).unwrap();
+        k9::snapshot!(world.objects.len(), "3");
+        //only the lamp box is sampled as a light
+        k9::snapshot!(lights.objects.len(), "1");
+
+        //unit box recentred, turned 45 degrees and lifted
+        let bbox = world.objects[1].bounding_box();
+        assert!((bbox.x.max - 0.5 * 2_f64.sqrt()).abs() < 1e-3);
+        assert!((bbox.y.min - 2.).abs() < 1e-3);
+
+        let reloaded = SceneDesc::from_toml(&scene.to_toml().unwrap()).unwrap();
+        k9::snapshot!(
+            reloaded.to_json().unwrap() == scene.to_json().unwrap(),
+            "true"
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let missing = SceneDesc::from_toml(
+            r#"
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            material = "nowhere"
+        "#,
+        )
+        .unwrap();
+        k9::snapshot!(
+            missing
+                .build_world(Path::new(""), &mut Vec::new())
+                .err()
+                .unwrap()
+                .to_string(),
+            "unknown material: nowhere"
+        );
+
+        let singular = SceneDesc::from_toml(
+            r#"
+            [materials.glass]
+            type = "dielectric"
+            ir = 1.5
+
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            material = "glass"
+            transform = [{ scale = [1, 0, 1] }]
+        "#,
+        )
+        .unwrap();
+        k9::snapshot!(
+            singular
+                .build_world(Path::new(""), &mut Vec::new())
+                .err()
+                .unwrap()
+                .to_string(),
+            "object transform is not invertible"
+        );
+
+        k9::snapshot!(
+            SceneDesc::from_toml("[[objects]]\ntype = \"cone\"").is_err(),
+            "true"
+        );
+        k9::snapshot!(
+            SceneDesc::load("scenes/three_spheres.yaml")
+                .err()
+                .unwrap()
+                .kind(),
+            "Unsupported"
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_cli {
+    use super::test_util::*;
+    use super::*;
+    use std::path::Path;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_view() {
+        assert_eq!(parse_args(args("")).unwrap(), Command::View { scene: None });
+        k9::snapshot!(
+            parse_args(args("scenes/three_spheres.toml"))
+                .unwrap()
+                .scene()
+                .unwrap()
+                .display(),
+            "scenes/three_spheres.toml"
+        );
+        k9::snapshot!(parse_args(args("a.toml b.toml")).is_err(), "true");
+    }
+
+    #[test]
+    fn test_parse_render() {
+        let command = parse_args(args(
+            "render scene.toml -w 320 --spp 64 --depth 8 -o out.ppm",
+        ))
+        .unwrap();
+        let Command::Render(options) = command else {
+            panic!("expected a render command");
+        };
+        k9::snapshot!(
+            &options,
+            r#"
+RenderOptions {
+    scene: Some(
+        "scene.toml",
+    ),
+    width: Some(
+        320,
+    ),
+    samples_per_pixel: Some(
+        64,
+    ),
+    max_depth: Some(
+        8,
+    ),
+    tone_map: None,
+    exposure: None,
+    white_point: None,
+    output: "out.ppm",
+}
+"#
+        );
+
+        let mut cam = Camera::default();
+        options.apply(&mut cam);
+        assert_eq!(
+            (cam.image_width, cam.samples_per_pixel, cam.max_depth),
+            (320, 64, 8)
+        );
+
+        //only the given overrides change the camera
+        let Command::Render(options) = parse_args(args("render --spp 2")).unwrap() else {
+            panic!("expected a render command");
+        };
+        k9::snapshot!(options.output.display(), "render.png");
+        let mut cam = Camera::default();
+        options.apply(&mut cam);
+        assert_eq!(
+            (cam.image_width, cam.samples_per_pixel, cam.max_depth),
+            (100, 2, 10)
+        );
+        k9::snapshot!(cam.tone_map == ToneMap::default(), "true");
+
+        let Command::Render(options) = parse_args(args(
+            "render -t extended_reinhard --exposure -1.5 --white 8",
+        ))
+        .unwrap() else {
+            panic!("expected a render command");
+        };
+        options.apply(&mut cam);
+        k9::snapshot!(
+            cam.tone_map,
+            "
+ToneMap {
+    operator: ExtendedReinhard,
+    exposure: -1.5,
+    white_point: 8.0,
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let message = |line: &str| {
+            let error = parse_args(args(line)).err().unwrap();
+            error.to_string().lines().next().unwrap().to_string()
+        };
+        k9::snapshot!(message("render --spp"), "missing value for --spp");
+        k9::snapshot!(
+            message("render --width 0"),
+            "--width expects a positive integer, got 0"
+        );
+        k9::snapshot!(message("render --fast"), "unknown option: --fast");
+        k9::snapshot!(
+            message("render --tonemap filmic"),
+            "unknown tone mapping operator: filmic"
+        );
+        k9::snapshot!(
+            message("render --exposure bright"),
+            "--exposure expects a number, got bright"
+        );
+        k9::snapshot!(
+            message("render a.toml b.toml"),
+            "unexpected argument: b.toml"
+        );
+    }
+
+    #[test]
+    fn test_progress_bar() {
+        k9::snapshot!(progress_bar(0, 4, 8), "[--------]   0% (0/4 rows)");
+        k9::snapshot!(progress_bar(1, 4, 8), "[##------]  25% (1/4 rows)");
+        k9::snapshot!(progress_bar(4, 4, 8), "[########] 100% (4/4 rows)");
+        k9::snapshot!(progress_bar(0, 0, 4), "[####] 100% (0/0 rows)");
+    }
+
+    #[test]
+    fn test_render_to_file() {
+        let output = temp_path("test_cli_render.png");
+        let options = RenderOptions {
+            scene: None,
+            width: Some(16),
+            samples_per_pixel: Some(1),
+            max_depth: Some(2),
+            tone_map: None,
+            exposure: None,
+            white_point: None,
+            output: output.clone(),
+        };
+        render_to_file(&options, &default_scene(), Path::new("")).unwrap();
+
+        //16 wide at 16:9
+        let image = Image::load(&output).unwrap();
+        assert_eq!((image.width(), image.height()), (16, 9));
+        std::fs::remove_file(&output).unwrap();
+
+        let options = RenderOptions {
+            output: temp_path("render.bmp"),
+            ..options
+        };
+        k9::snapshot!(
+            render_to_file(&options, &default_scene(), Path::new(""))
+                .err()
+                .unwrap()
+                .kind(),
+            "Unsupported"
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_image {
+    use super::test_util::*;
+    use super::*;
+
+    fn gradient() -> Image {
+        Image::new_with_init(3, 5, |row, col| Pixel {
+            r: (col * 60) as u8,
+            g: (row * 100) as u8,
+            b: 7,
+        })
+    }
+
+    #[test]
+    fn test_png_round_trip() {
+        let png = gradient().to_png().unwrap();
+        k9::snapshot!(&png[1..4] == b"PNG", "true");
+
+        let img = Image::from_png(&png).unwrap();
+        assert_eq!((img.width(), img.height()), (5, 3));
+        k9::snapshot!(img.to_rgb_bytes() == gradient().to_rgb_bytes(), "true");
+        k9::snapshot!(Image::from_png(&png[..png.len() / 2]).is_err(), "true");
+    }
+
+    #[test]
+    fn test_png_gray_16_bit() {
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, 2, 1);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Sixteen);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&[0xff, 0xff, 0x80, 0x00]).unwrap();
+        writer.finish().unwrap();
+
+        let img = Image::from_png(&png).unwrap();
+        k9::snapshot!(PPM(&img.pixels[0][0]), "255 255 255");
+        k9::snapshot!(PPM(&img.pixels[0][1]), "128 128 128");
+    }
+
+    #[test]
+    fn test_save_by_extension() {
+        for name in ["test_image.png", "test_image.PPM"] {
+            let path = temp_path(name);
+            gradient().save(&path).unwrap();
+            let img = Image::load(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            k9::snapshot!(img.to_rgb_bytes() == gradient().to_rgb_bytes(), "true");
+        }
+
+        let path = std::path::Path::new("image.tga");
+        k9::snapshot!(ImageFormat::from_path(path), "None");
+        k9::snapshot!(
+            gradient().save(path).err().unwrap().to_string(),
+            "unsupported image format: image.tga"
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_hdr {
+    use super::test_util::*;
+    use super::*;
+
+    fn bright() -> HdrImage {
+        HdrImage::new_with_init(2, 3, |row, col| {
+            Color::new(col as f64 * 2., row as f64 + 0.25, 100.)
+        })
+    }
+
+    #[test]
+    fn test_render_keeps_radiance() {
+        let mut cam = Camera::default();
+        cam.image_width = 4;
+        cam.samples_per_pixel = 2;
+        cam.background = Some(Color::new(4., 0.5, 0.));
+        let world = HittableList::new();
+
+        let (hdr, stats) =
+            cam.parallel_render_hdr_with_progress(&world, &HittableList::new(), |_, _| {});
+        assert_eq!((hdr.width(), hdr.height()), (4, 4));
+        k9::snapshot!(stats.paths, "32");
+        k9::snapshot!(hdr.get(3, 2), "(4, 0.5, 0)");
+        k9::snapshot!(cam.render_hdr(&world, &HittableList::new()) == hdr, "true");
+
+        //the 8-bit path clamps and applies the sRGB curve
+        k9::snapshot!(PPM(&hdr.to_image().pixels[0][0]), "255 188   0");
+        k9::snapshot!(
+            PPM(&cam.parallel_render(&world, &HittableList::new()).pixels[0][0]),
+            "255 188   0"
+        );
+    }
+
+    #[test]
+    fn test_accumulate() {
+        let constant = |value: f64| HdrImage::new_with_init(1, 2, |_, _| Color::new(value, 0., 1.));
+
+        //one sample of 1, then three samples averaging 3
+        let mut running = constant(1.);
+        running.accumulate(&constant(3.), 1, 3);
+        k9::snapshot!(running.get(0, 1), "(2.5, 0, 1)");
+        running.accumulate(&constant(0.), 4, 4);
+        k9::snapshot!(running.get(0, 0), "(1.25, 0, 1)");
+    }
+
+    #[test]
+    fn test_progressive_passes() {
+        //noisy per pixel, but passes of 1 sample converge to the same mean as one big render
+        let mut cam = Camera::default();
+        cam.image_width = 4;
+        cam.samples_per_pixel = 64;
+        let mut world = HittableList::new();
+        world.add(Arc::new(Sphere::new(
+            Point3::new(0., 0., 0.),
+            0.5,
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        )));
+
+        let mut running = cam.parallel_render_pass(&world, &HittableList::new(), 1);
+        for pass in 1..64 {
+            running.accumulate(
+                &cam.parallel_render_pass(&world, &HittableList::new(), 1),
+                pass,
+                1,
+            );
+        }
+        let full = cam.parallel_render_hdr(&world, &HittableList::new());
+
+        let mean = |image: &HdrImage| {
+            (0..4)
+                .flat_map(|row| (0..4).map(move |col| (row, col)))
+                .map(|(row, col)| image.get(row, col).y)
+                .sum::<f64>()
+                / 16.
+        };
+        assert!((mean(&running) - mean(&full)).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_cancelled_pass() {
+        let mut cam = Camera::default();
+        cam.image_width = 4;
+        let world = HittableList::new();
+        let lights = HittableList::new();
+
+        let pass = cam.parallel_render_pass_cancellable(&world, &lights, 1, || false);
+        assert_eq!(pass.map(|pass| (pass.width(), pass.height())), Some((4, 4)));
+        let pass = cam.parallel_render_pass_cancellable(&world, &lights, 1, || true);
+        k9::snapshot!(pass.is_none(), "true");
+
+        //cancelled halfway through
+        let rows = std::sync::atomic::AtomicUsize::new(0);
+        let cancelled = || rows.fetch_add(1, std::sync::atomic::Ordering::Relaxed) >= 2;
+        k9::snapshot!(
+            cam.parallel_render_pass_cancellable(&world, &lights, 1, cancelled)
+                .is_none(),
+            "true"
+        );
+    }
+
+    #[test]
+    fn test_same_view() {
+        let cam = Camera::default();
+        let mut other = cam.clone();
+        other.samples_per_pixel = 500;
+        other.tone_map.exposure = 2.;
+        k9::snapshot!(cam.same_view(&other), "true");
+
+        other.lookfrom = Point3::new(0., 1., -1.);
+        k9::snapshot!(cam.same_view(&other), "false");
+
+        let mut other = cam.clone();
+        other.background = Some(Color::new(0., 0., 0.));
+        k9::snapshot!(cam.same_view(&other), "false");
+    }
+
+    #[test]
+    fn test_pfm_round_trip() {
+        let pfm = bright().to_pfm();
+        k9::snapshot!(&pfm[..12] == b"PF\n3 2\n-1.0\n", "true");
+        //bottom row first
+        k9::snapshot!(
+            f32::from_le_bytes([pfm[16], pfm[17], pfm[18], pfm[19]]),
+            "1.25"
+        );
+
+        let img = HdrImage::from_pfm(&pfm).unwrap();
+        k9::snapshot!(img == bright(), "true");
+        k9::snapshot!(HdrImage::from_pfm(&pfm[..pfm.len() - 1]).is_err(), "true");
+        k9::snapshot!(
+            HdrImage::from_pfm(b"Pf\n1 1\n-1.0\n\0\0\0\0").is_err(),
+            "true"
+        );
+        //a crafted header must not overflow the raster size
+        let huge = format!("PF\n{} {}\n-1.0\n", usize::MAX, 2);
+        k9::snapshot!(
+            HdrImage::from_pfm(huge.as_bytes()).unwrap_err(),
+            "
+Custom {
+    kind: InvalidData,
+    error: \"PFM dimensions too large\",
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_radiance_hdr() {
+        let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 3\n";
+        let hdr = bright().to_hdr();
+        k9::snapshot!(&hdr[..header.len()] == header, "true");
+
+        //too narrow for run-length scanlines, pixels are plain RGBE
+        let decode = |rgbe: &[u8]| {
+            let scale = 2f64.powi(rgbe[3] as i32 - 128 - 8);
+            Color::new(
+                rgbe[0] as f64 * scale,
+                rgbe[1] as f64 * scale,
+                rgbe[2] as f64 * scale,
+            )
+        };
+        let row1 = header.len() + 12;
+        //8-bit mantissas shared with the brightest component
+        k9::snapshot!(decode(&hdr[row1 + 8..row1 + 12]), "(4, 1, 100)");
+        assert_eq!(hdr.len(), header.len() + 2 * 3 * 4);
+
+        //wide images use literal runs per component
+        let wide = HdrImage::new_with_init(1, 200, |row, col| Color::new(0.5, 0.5, 0.5)).to_hdr();
+        let scanline = &wide[wide.iter().position(|&b| b == 2).unwrap()..];
+        assert_eq!(scanline[..6], [2, 2, 0, 200, 128, 128]);
+        assert_eq!(scanline.len(), 4 + 4 * (2 + 200));
+    }
+
+    #[test]
+    fn test_save_by_extension() {
+        for name in ["test_hdr.exr", "test_hdr.pfm"] {
+            let path = temp_path(name);
+            bright().save(&path).unwrap();
+            let img = HdrImage::load(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            k9::snapshot!(img == bright(), "true");
+        }
+
+        let path = temp_path("test_hdr.hdr");
+        bright().save(&path).unwrap();
+        k9::snapshot!(std::fs::read(&path).unwrap() == bright().to_hdr(), "true");
+        std::fs::remove_file(&path).unwrap();
+
+        let path = std::path::Path::new("image.png");
+        k9::snapshot!(HdrFormat::from_path(path), "None");
+        k9::snapshot!(
+            bright().save(path).err().unwrap().to_string(),
+            "unsupported HDR image format: image.png"
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_tonemap {
+    use super::test_util::*;
+    use super::*;
+
+    #[test]
+    fn test_srgb() {
+        k9::snapshot!(linear_to_srgb(0.), "0.0");
+        assert!((linear_to_srgb(1.) - 1.).abs() < 1e-12);
+        //linear segment near black
+        k9::snapshot!(linear_to_srgb(0.0025), "0.0323");
+        assert!((linear_to_srgb(0.5) - 0.735357).abs() < 1e-6);
+        for x in [0.0001, 0.002, 0.04, 0.2, 0.9] {
+            assert!((srgb_to_linear(linear_to_srgb(x)) - x).abs() < 1e-12);
+        }
+        //both pieces meet at the threshold
+        let below = linear_to_srgb(0.0031308);
+        let above = linear_to_srgb(0.0031308 + 1e-12);
+        assert!((below - above).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_operators() {
+        let map = |operator: ToneMapOperator, x: f64| ToneMap::new(operator).map(x);
+
+        k9::snapshot!(map(ToneMapOperator::Clamp, 0.25), "0.25");
+        k9::snapshot!(map(ToneMapOperator::Clamp, 3.), "1.0");
+        k9::snapshot!(map(ToneMapOperator::Reinhard, 1.), "0.5");
+        k9::snapshot!(map(ToneMapOperator::ExtendedReinhard, 4.), "1.0");
+        k9::snapshot!(map(ToneMapOperator::Uncharted2, 5.6), "1.0");
+        assert!((map(ToneMapOperator::Aces, 1.) - 0.8038).abs() < 1e-4);
+
+        for operator in ToneMapOperator::ALL {
+            k9::snapshot!(map(operator, 0.), "0.0");
+            k9::snapshot!(map(operator, -1.), "0.0");
+            assert!(map(operator, 1e9) > 0.999);
+            //monotonic
+            let mut last = 0.;
+            for i in 1..200 {
+                let y = map(operator, i as f64 * 0.05);
+                assert!(y >= last, "{:?} decreases at {}", operator, i);
+                last = y;
+            }
+            k9::snapshot!(
+                ToneMapOperator::from_name(operator.name()) == Some(operator),
+                "true"
+            );
+        }
+    }
+
+    #[test]
+    fn test_exposure() {
+        let mut tone_map = ToneMap::new(ToneMapOperator::Reinhard);
+        tone_map.exposure = 2.;
+        k9::snapshot!(tone_map.map(0.25), "0.5");
+        tone_map.exposure = -1.;
+        k9::snapshot!(tone_map.map(2.), "0.5");
+    }
+
+    #[test]
+    fn test_apply() {
+        let hdr = HdrImage::new_with_init(1, 2, |row, col| Color::new(0.5, 8., 0.));
+
+        k9::snapshot!(
+            PPM(&ToneMap::default().apply(&hdr).pixels[0][0]),
+            "188 255   0"
+        );
+        let aces = ToneMap::new(ToneMapOperator::Aces).apply(&hdr);
+        k9::snapshot!(PPM(&aces.pixels[0][1]), "206 255   0");
+
+        //decoding an sRGB pixel gives back the linear value
+        let pixel = ToneMap::default().to_pixel(&Color::new(0.5, 0.5, 0.5));
+        assert!((pixel.to_color().x - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_save() {
+        let hdr = HdrImage::new_with_init(1, 2, |row, col| Color::new(0.5, 8., 0.));
+
+        //8-bit formats are tone mapped
+        let png = temp_path("test_tonemap_save.png");
+        ToneMap::default().save(&hdr, &png).unwrap();
+        let loaded = Image::load(&png).unwrap();
+        std::fs::remove_file(&png).unwrap();
+        k9::snapshot!(PPM(&loaded.pixels[0][1]), "188 255   0");
+
+        //float formats keep the radiance
+        let pfm = temp_path("test_tonemap_save.pfm");
+        ToneMap::default().save(&hdr, &pfm).unwrap();
+        let loaded = HdrImage::load(&pfm).unwrap();
+        std::fs::remove_file(&pfm).unwrap();
+        assert_eq!(loaded, hdr);
+
+        let error = ToneMap::default()
+            .save(&hdr, temp_path("test_tonemap_save.bmp"))
+            .unwrap_err();
+        k9::snapshot!(error.kind(), "Unsupported");
+    }
+}
+
+#[cfg(test)]
+mod test_navigation {
+    use super::test_util::*;
+    use super::*;
+
+    fn cam() -> Camera {
+        let mut cam = Camera::default();
+        cam.lookfrom = Point3::new(0., 0., 5.);
+        cam.lookat = Point3::new(0., 0., 0.);
+        cam
+    }
+
+    #[test]
+    fn test_orbit() {
+        let mut cam = cam();
+        cam.orbit(90., 0.);
+        assert_near(&cam.lookfrom, &Point3::new(5., 0., 0.));
+        assert_near(&cam.lookat, &Point3::default());
+
+        cam.orbit(0., 45.);
+        let up = 5. * (PI / 4.).sin();
+        assert_near(&cam.lookfrom, &Point3::new(up, up, 0.));
+
+        //stops one degree short of looking straight down
+        cam.orbit(0., 80.);
+        let offset = &cam.lookfrom - &cam.lookat;
+        k9::snapshot!(offset.unit().y.acos().to_degrees().round(), "1.0");
+        assert!((offset.length() - 5.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_zoom_and_dolly() {
+        let mut cam = cam();
+        cam.zoom(0.5);
+        k9::snapshot!(cam.vfov, "45.0");
+        cam.zoom(100.);
+        k9::snapshot!(cam.vfov, "179.0");
+
+        cam.dolly(0.5);
+        assert_near(&cam.lookfrom, &Point3::new(0., 0., 2.5));
+        cam.dolly(0.);
+        assert!(cam.lookfrom.z > 0.);
+    }
+
+    #[test]
+    fn test_pan_and_fly() {
+        let mut cam = cam();
+        cam.pan(1., 2.);
+        assert_near(&cam.lookfrom, &Point3::new(1., 2., 5.));
+        assert_near(&cam.lookat, &Point3::new(1., 2., 0.));
+
+        let mut cam = self::cam();
+        cam.fly(1., 0., 0.);
+        assert_near(&cam.lookfrom, &Point3::new(0., 0., 4.));
+        cam.fly(0., 1., -1.);
+        assert_near(&cam.lookfrom, &Point3::new(1., -1., 4.));
+        assert_near(&cam.lookat, &Point3::new(1., -1., -1.));
+        k9::snapshot!(cam.same_view(&self::cam()), "false");
+    }
+}
+
+#[cfg(test)]
+mod test_hittable {
+    use super::*;
+
+    #[test]
+    fn test_hit() {
+        // let h1 = Hittable::new();
+        let h1 = 2;
+        k9::snapshot!(h1, "2");
+    }
+}
+
+#[cfg(test)]
+mod test_util {
+    use super::*;
+
+    pub fn empty_record() -> HitRecord {
+        HitRecord::new(Arc::new(Lambertian::new(Color::default())))
+    }
+
+    pub fn ray_t() -> Interval {
+        Interval::new_with_init(0.001, INFINITY)
+    }
+
+    pub fn assert_near(a: &Vec3, b: &Vec3) {
+        assert!((a - b).length() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    //file in the shared temp directory, the process id keeps concurrent test runs apart
+    pub fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("ray_tracer_{}_{}", std::process::id(), name))
+    }
+}
+
+#[cfg(test)]
+mod test_bvh {
+    use super::test_util::*;
+    use super::*;
+
+    fn random_spheres(count: usize) -> HittableList {
+        let mut world = HittableList::new();
+        let material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        for _ in 0..count {
+            world.add(Arc::new(Sphere::new(
+                Point3::random_in_range(-10., 10.),
+                random_f64_in_range(0.1, 1.),
+                material.clone(),
+            )));
+        }
+        world
+    }
+
+    #[test]
+    fn test_aabb_hit() {
+        let bbox = Aabb::new_from_points(&Point3::new(1., 1., 1.), &Point3::new(-1., -1., -1.));
+        let toward = Ray::new(Point3::new(0., 0., -5.), Vec3::new(0., 0., 1.));
+        let away = Ray::new(Point3::new(0., 0., -5.), Vec3::new(0., 0., -1.));
+        let beside = Ray::new(Point3::new(2., 0., -5.), Vec3::new(0., 0., 1.));
+        let universe = Interval::new_with_init(0.001, INFINITY);
+
+        k9::snapshot!(bbox.hit(&toward, universe), "true");
+        k9::snapshot!(bbox.hit(&away, universe), "false");
+        k9::snapshot!(bbox.hit(&beside, universe), "false");
+        k9::snapshot!(
+            bbox.hit(&toward, Interval::new_with_init(0.001, 3.)),
+            "false"
+        );
+    }
+
+    #[test]
+    fn test_sphere_bounding_box() {
+        let material = Arc::new(Dielectric::new(1.5));
+        let hollow = Sphere::new(Point3::new(-1., 0., -1.), -0.4, material);
+        k9::snapshot!(
+            hollow.bounding_box(),
+            "
+Aabb {
+    x: Interval {
+        min: -1.4,
+        max: -0.6,
+    },
+    y: Interval {
+        min: -0.4,
+        max: 0.4,
+    },
+    z: Interval {
+        min: -1.4,
+        max: -0.6,
+    },
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_bvh_matches_list() {
+        let world = random_spheres(200);
+        let bvh = BvhNode::new(&world);
+
+        for _ in 0..2000 {
+            let r = Ray::new(
+                Point3::random_in_range(-15., 15.),
+                Vec3::random_unit_vector(),
+            );
+            let ray_t = Interval::new_with_init(0.001, INFINITY);
+            let list_rec = &mut empty_record();
+            let bvh_rec = &mut empty_record();
+
+            let list_hit = world.hit(&r, ray_t, list_rec);
+            let bvh_hit = bvh.hit(&r, ray_t, bvh_rec);
+
+            assert_eq!(list_hit, bvh_hit);
+            if list_hit {
+                assert_eq!(list_rec.t, bvh_rec.t);
+                assert_eq!(list_rec.front_face, bvh_rec.front_face);
+            }
+        }
+    }
+
+    //spheres packed into a few tight clusters, laid out without randomness
+    fn clustered_spheres() -> HittableList {
+        let mut world = HittableList::new();
+        let material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        for i in 0..600 {
+            let cluster = Point3::new((i % 3) as f64 * 20., 0., (i % 5) as f64 * -7.);
+            let offset = Vec3::new(
+                (i as f64 * 0.37).sin() * 2.,
+                (i as f64 * 0.71).cos() * 2.,
+                (i as f64 * 1.13).sin() * 2.,
+            );
+            world.add(Arc::new(Sphere::new(
+                cluster + offset,
+                0.2,
+                material.clone(),
+            )));
+        }
+        world
+    }
+
+    #[test]
+    fn test_flat_bvh_matches_list() {
+        let world = random_spheres(300);
+        let middle = FlatBvh::new(&world, SplitMethod::Middle);
+        let sah = FlatBvh::new(&world, SplitMethod::Sah);
+
+        for _ in 0..2000 {
+            let r = Ray::new(
+                Point3::random_in_range(-15., 15.),
+                Vec3::random_unit_vector(),
+            );
+            let ray_t = Interval::new_with_init(0.001, INFINITY);
+            let list_rec = &mut empty_record();
+            let list_hit = world.hit(&r, ray_t, list_rec);
+
+            for bvh in [&middle, &sah] {
+                let bvh_rec = &mut empty_record();
+                assert_eq!(list_hit, bvh.hit(&r, ray_t, bvh_rec));
+                if list_hit {
+                    assert_eq!(list_rec.t, bvh_rec.t);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_flat_bvh_stats() {
+        let world = clustered_spheres();
+        let middle = FlatBvh::new(&world, SplitMethod::Middle);
+        let sah = FlatBvh::new(&world, SplitMethod::Sah);
+
+        for stats in [middle.stats(), sah.stats()] {
+            assert_eq!(stats.primitive_count, 600);
+            assert_eq!(stats.node_count, 2 * stats.leaf_count - 1);
+            assert!(stats.min_leaf_size >= 1);
+            assert!(stats.max_depth < 64);
+        }
+        assert!(sah.stats().max_leaf_size <= 4);
+        assert!(sah.stats().sah_cost < middle.stats().sah_cost);
+    }
+
+    #[test]
+    fn test_flat_bvh_empty() {
+        let bvh = FlatBvh::new(&HittableList::new(), SplitMethod::Sah);
+        let r = Ray::new(Point3::default(), Vec3::new(0., 0., 1.));
+        let hit = bvh.hit(
+            &r,
+            Interval::new_with_init(0.001, INFINITY),
+            &mut empty_record(),
+        );
+        k9::snapshot!(hit, "false");
+        k9::snapshot!(bvh.stats().node_count, "0");
     }
 }
 
@@ -1,6 +1,8 @@
 #![allow(unused_assignments)]
-use crate::{hittable::*, random_f64, Color, Ray, Vec3};
-pub trait Material: Sync {
+use crate::{hittable::*, pdf::*, random_f64, texture::*, Color, Point3, Ray, Vec3};
+use std::sync::Arc;
+
+pub trait Material: Send + Sync {
     fn scatter(
         &self,
         r_in: &Ray,
@@ -10,15 +12,39 @@ pub trait Material: Sync {
     ) -> bool {
         false
     }
+
+    fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
+        Color::new(0., 0., 0.)
+    }
+
+    //BSDF times the cosine term (the phase function for media) for light arriving along
+    //direction and leaving along -r_in.direction, over scattering_pdf it gives the attenuation
+    fn bsdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        Color::new(0., 0., 0.)
+    }
+
+    //solid angle density of scatter picking direction
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        0.
+    }
+
+    //perfect mirrors and glass have no bsdf to sample lights with
+    fn is_specular(&self) -> bool {
+        true
+    }
 }
 
 pub struct Lambertian {
-    albedo: Color,
+    tex: Arc<dyn Texture>,
 }
 
 impl Lambertian {
     pub fn new(albedo: Color) -> Self {
-        Self { albedo }
+        Self::new_with_texture(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn new_with_texture(tex: Arc<dyn Texture>) -> Self {
+        Self { tex }
     }
 }
 
@@ -30,29 +56,45 @@ impl Material for Lambertian {
         attenuation: &mut Color,
         scattered: &mut Ray,
     ) -> bool {
-        let mut scatter_direction = rec.normal.clone() + Vec3::random_unit_vector();
-
-        if scatter_direction.near_zero() {
-            scatter_direction = rec.normal.clone();
-        }
+        let scatter_direction = CosinePdf::new(&rec.normal).generate();
 
-        *scattered = Ray::new(rec.p.clone(), scatter_direction.clone());
-        *attenuation = self.albedo.clone();
+        *scattered = Ray::new_with_time(rec.p.clone(), scatter_direction, r_in.time);
+        *attenuation = self.tex.value(rec.u, rec.v, &rec.p);
         true
     }
+
+    fn bsdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        self.scattering_pdf(r_in, rec, direction) * self.tex.value(rec.u, rec.v, &rec.p)
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        CosinePdf::new(&rec.normal).value(direction)
+    }
+
+    fn is_specular(&self) -> bool {
+        false
+    }
 }
 pub struct Metal {
-    albedo: Color,
+    tex: Arc<dyn Texture>,
     f: f64,
 }
 
 impl Metal {
     pub fn new(albedo: Color, f: f64) -> Self {
+        Self::new_with_texture(Arc::new(SolidColor::new(albedo)), f)
+    }
+
+    pub fn new_with_texture(tex: Arc<dyn Texture>, f: f64) -> Self {
         Self {
-            albedo,
+            tex,
             f: if f < 1. { f } else { 1. },
         }
     }
+
+    fn lobe(&self, r_in: &Ray, rec: &HitRecord) -> FuzzPdf {
+        FuzzPdf::new(&Vec3::reflect(&r_in.direction.unit(), &rec.normal), self.f)
+    }
 }
 
 impl Material for Metal {
@@ -63,15 +105,27 @@ impl Material for Metal {
         attenuation: &mut Color,
         scattered: &mut Ray,
     ) -> bool {
-        let reflected = Vec3::reflect(&r_in.direction.unit(), &rec.normal);
+        *scattered = Ray::new_with_time(rec.p.clone(), self.lobe(r_in, rec).generate(), r_in.time);
+        *attenuation = self.tex.value(rec.u, rec.v, &rec.p);
 
-        *scattered = Ray::new(
-            rec.p.clone(),
-            reflected + self.f * Vec3::random_unit_vector(),
-        );
-        *attenuation = self.albedo.clone();
+        scattered.direction.dot(&rec.normal) > 0.
+    }
 
-        return scattered.direction.dot(&rec.normal) > 0.;
+    //directions below the surface are absorbed
+    fn bsdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        if direction.dot(&rec.normal) <= 0. {
+            return Color::new(0., 0., 0.);
+        }
+        self.scattering_pdf(r_in, rec, direction) * self.tex.value(rec.u, rec.v, &rec.p)
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        self.lobe(r_in, rec).value(direction)
+    }
+
+    //only a perfect mirror is a delta distribution
+    fn is_specular(&self) -> bool {
+        self.f == 0.
     }
 }
 pub struct Dielectric {
@@ -117,8 +171,70 @@ impl Material for Dielectric {
 
         let refracted = Vec3::refract(&unit_direction, &rec.normal, refraction_ratio);
 
-        *scattered = Ray::new(rec.p.clone(), direction);
-        return true;
+        *scattered = Ray::new_with_time(rec.p.clone(), direction, r_in.time);
+        true
+    }
+}
+
+//emits light from its texture and absorbs every incoming ray
+pub struct DiffuseLight {
+    tex: Arc<dyn Texture>,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> Self {
+        Self::new_with_texture(Arc::new(SolidColor::new(emit)))
+    }
+
+    pub fn new_with_texture(tex: Arc<dyn Texture>) -> Self {
+        Self { tex }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.tex.value(u, v, p)
+    }
+}
+
+//phase function of a participating medium, scatters uniformly in all directions
+pub struct Isotropic {
+    tex: Arc<dyn Texture>,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Color) -> Self {
+        Self::new_with_texture(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn new_with_texture(tex: Arc<dyn Texture>) -> Self {
+        Self { tex }
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> bool {
+        *scattered = Ray::new_with_time(rec.p.clone(), SpherePdf.generate(), r_in.time);
+        *attenuation = self.tex.value(rec.u, rec.v, &rec.p);
+        true
+    }
+
+    fn bsdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        self.scattering_pdf(r_in, rec, direction) * self.tex.value(rec.u, rec.v, &rec.p)
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        SpherePdf.value(direction)
+    }
+
+    fn is_specular(&self) -> bool {
+        false
     }
 }
 
@@ -126,5 +242,5 @@ fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
     //Shlick approximation for reflectance
     let mut r0 = (1. - ref_idx) / (1. + ref_idx);
     r0 = r0 * r0;
-    return r0 + (1. - r0) * (1. - cosine).powi(5);
+    r0 + (1. - r0) * (1. - cosine).powi(5)
 }
@@ -0,0 +1,273 @@
+use crate::hittable_list::*;
+use crate::material::*;
+use crate::texture::*;
+use crate::triangle_mesh::*;
+use crate::vec3::*;
+use crate::Color;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+//loads a Wavefront .obj file as one TriangleMesh per material
+//mtllib paths are resolved relative to the .obj file, problems that don't stop the model
+//from loading are added to warnings for the caller to report
+pub fn load_obj(
+    path: impl AsRef<Path>,
+    smooth_shading: bool,
+    warnings: &mut Vec<String>,
+) -> io::Result<HittableList> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path)?;
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    parse_obj(&source, base_dir, smooth_shading, warnings)
+}
+
+pub fn parse_obj(
+    source: &str,
+    base_dir: &Path,
+    smooth_shading: bool,
+    warnings: &mut Vec<String>,
+) -> io::Result<HittableList> {
+    let mut buffers = MeshBuffers::default();
+    let mut materials: HashMap<String, Arc<dyn Material>> = HashMap::new();
+    //faces grouped by material name, in order of first use
+    let mut groups: Vec<(String, Vec<MeshFace>)> = Vec::new();
+    let mut current_material = String::new();
+
+    for (line_number, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("");
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let args: Vec<&str> = tokens.collect();
+        let error = |message: &str| parse_error("obj", line_number, message);
+
+        match keyword {
+            "v" => buffers
+                .positions
+                .push(parse_vec3(&args).ok_or_else(|| error("bad vertex"))?),
+            "vn" => buffers
+                .normals
+                .push(parse_vec3(&args).ok_or_else(|| error("bad normal"))?),
+            "vt" => {
+                let u = parse_f64(args.first()).ok_or_else(|| error("bad texture coordinate"))?;
+                let v = parse_f64(args.get(1)).unwrap_or(0.);
+                buffers.uvs.push((u, v));
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(error("face needs at least 3 vertices"));
+                }
+                let corners = args
+                    .iter()
+                    .map(|corner| parse_face_corner(corner, &buffers))
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| error("bad face index"))?;
+
+                let group = match groups
+                    .iter()
+                    .position(|(name, _)| *name == current_material)
+                {
+                    Some(group) => group,
+                    None => {
+                        groups.push((current_material.clone(), Vec::new()));
+                        groups.len() - 1
+                    }
+                };
+                let faces = &mut groups[group].1;
+
+                //polygons are split into a fan around the first corner
+                for i in 1..corners.len() - 1 {
+                    let [a, b, c] = [&corners[0], &corners[i], &corners[i + 1]];
+                    faces.push(MeshFace {
+                        vertices: [a.0, b.0, c.0],
+                        uvs: a.1.zip(b.1).zip(c.1).map(|((a, b), c)| [a, b, c]),
+                        normals: a.2.zip(b.2).zip(c.2).map(|((a, b), c)| [a, b, c]),
+                    });
+                }
+            }
+            "usemtl" => current_material = args.join(" "),
+            "mtllib" => {
+                for file in args {
+                    let path = base_dir.join(file);
+                    let mtl_source = fs::read_to_string(&path)?;
+                    let mtl_dir = path.parent().unwrap_or(base_dir);
+                    materials.extend(parse_mtl(&mtl_source, mtl_dir, warnings)?);
+                }
+            }
+            //groups, objects and smoothing groups don't change how faces are built
+            _ => {}
+        }
+    }
+
+    let buffers = Arc::new(buffers);
+    let default_material: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.8)));
+
+    let mut world = HittableList::new();
+    for (name, faces) in groups {
+        let material = materials
+            .get(&name)
+            .cloned()
+            .unwrap_or_else(|| default_material.clone());
+        world.add(Arc::new(TriangleMesh::new(
+            buffers.clone(),
+            faces,
+            material,
+            smooth_shading,
+        )));
+    }
+
+    Ok(world)
+}
+
+//maps .mtl materials onto Dielectric for transparent ones, Metal for mirror-like ones
+//and Lambertian otherwise, map_Kd is used as the Lambertian texture when it can be loaded
+//and otherwise a warning is added for the caller to report
+pub fn parse_mtl(
+    source: &str,
+    base_dir: &Path,
+    warnings: &mut Vec<String>,
+) -> io::Result<HashMap<String, Arc<dyn Material>>> {
+    #[derive(Default)]
+    struct MtlEntry {
+        kd: Option<Color>,
+        ks: Option<Color>,
+        ns: Option<f64>,
+        ni: Option<f64>,
+        dissolve: Option<f64>,
+        illum: Option<u32>,
+        map_kd: Option<String>,
+    }
+
+    let mut entries: Vec<(String, MtlEntry)> = Vec::new();
+
+    for (line_number, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("");
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let args: Vec<&str> = tokens.collect();
+        let error = |message: &str| parse_error("mtl", line_number, message);
+
+        if keyword == "newmtl" {
+            entries.push((args.join(" "), MtlEntry::default()));
+            continue;
+        }
+        let entry = match entries.last_mut() {
+            Some((_, entry)) => entry,
+            None => continue,
+        };
+
+        match keyword {
+            "Kd" => entry.kd = Some(parse_vec3(&args).ok_or_else(|| error("bad Kd"))?),
+            "Ks" => entry.ks = Some(parse_vec3(&args).ok_or_else(|| error("bad Ks"))?),
+            "Ns" => entry.ns = Some(parse_f64(args.first()).ok_or_else(|| error("bad Ns"))?),
+            "Ni" => entry.ni = Some(parse_f64(args.first()).ok_or_else(|| error("bad Ni"))?),
+            "d" => entry.dissolve = Some(parse_f64(args.first()).ok_or_else(|| error("bad d"))?),
+            "Tr" => {
+                let tr = parse_f64(args.first()).ok_or_else(|| error("bad Tr"))?;
+                entry.dissolve = Some(1. - tr);
+            }
+            "illum" => {
+                entry.illum = Some(
+                    args.first()
+                        .and_then(|arg| arg.parse().ok())
+                        .ok_or_else(|| error("bad illum"))?,
+                )
+            }
+            "map_Kd" => entry.map_kd = args.last().map(|file| file.to_string()),
+            _ => {}
+        }
+    }
+
+    let mut materials: HashMap<String, Arc<dyn Material>> = HashMap::new();
+    for (name, entry) in entries {
+        let transparent = matches!(entry.illum, Some(4) | Some(6) | Some(7) | Some(9))
+            || entry.dissolve.is_some_and(|d| d < 1.);
+        let mirror = matches!(entry.illum, Some(3) | Some(5));
+
+        let material: Arc<dyn Material> = if transparent {
+            Arc::new(Dielectric::new(entry.ni.unwrap_or(1.5)))
+        } else if mirror {
+            //shininess runs from 0 (rough) to 1000 (polished)
+            let fuzz = 1. - entry.ns.unwrap_or(1000.).clamp(0., 1000.) / 1000.;
+            let albedo = entry.ks.or(entry.kd).unwrap_or(Color::new(1., 1., 1.));
+            Arc::new(Metal::new(albedo, fuzz))
+        } else {
+            //a texture that can't be read shouldn't lose the whole model
+            let texture = entry.map_kd.and_then(|file| {
+                let path = base_dir.join(file);
+                ImageTexture::load(&path)
+                    .map_err(|error| {
+                        warnings.push(format!("{}: {}, using Kd instead", path.display(), error))
+                    })
+                    .ok()
+            });
+            match texture {
+                Some(texture) => Arc::new(Lambertian::new_with_texture(Arc::new(texture))),
+                None => Arc::new(Lambertian::new(
+                    entry.kd.unwrap_or(Color::new(0.8, 0.8, 0.8)),
+                )),
+            }
+        };
+        materials.insert(name, material);
+    }
+
+    Ok(materials)
+}
+
+fn parse_error(kind: &str, line_number: usize, message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{} line {}: {}", kind, line_number + 1, message),
+    )
+}
+
+fn parse_f64(arg: Option<&&str>) -> Option<f64> {
+    arg.and_then(|arg| arg.parse().ok())
+}
+
+fn parse_vec3(args: &[&str]) -> Option<Vec3> {
+    Some(Vec3::new(
+        parse_f64(args.first())?,
+        parse_f64(args.get(1))?,
+        parse_f64(args.get(2))?,
+    ))
+}
+
+//resolves a 1-based (or negative, relative) obj index into the given buffer length
+fn resolve_index(index: &str, len: usize) -> Option<usize> {
+    let index: i64 = index.parse().ok()?;
+    let resolved = if index < 0 {
+        len as i64 + index
+    } else {
+        index - 1
+    };
+    (0..len as i64)
+        .contains(&resolved)
+        .then_some(resolved as usize)
+}
+
+//parses v, v/vt, v//vn or v/vt/vn into position, uv and normal indices
+fn parse_face_corner(
+    corner: &str,
+    buffers: &MeshBuffers,
+) -> Option<(usize, Option<usize>, Option<usize>)> {
+    let mut parts = corner.split('/');
+    let position = resolve_index(parts.next()?, buffers.positions.len())?;
+    let uv = match parts.next() {
+        Some("") | None => None,
+        Some(index) => Some(resolve_index(index, buffers.uvs.len())?),
+    };
+    let normal = match parts.next() {
+        Some("") | None => None,
+        Some(index) => Some(resolve_index(index, buffers.normals.len())?),
+    };
+    Some((position, uv, normal))
+}
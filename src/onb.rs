@@ -0,0 +1,143 @@
+use crate::utility::*;
+use crate::vec3::*;
+
+//GGX roughness below this is a perfect mirror, whose density has no finite value
+const MIN_GGX_ALPHA: f64 = 1e-3;
+
+//right-handed orthonormal basis with w along a given direction, sampling routines generate
+//directions around w and return them in world space together with their solid angle density
+#[derive(Clone, Debug)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    pub fn new(w: &Vec3) -> Self {
+        let w = w.unit();
+        let helper = if w.x.abs() > 0.9 {
+            Vec3::new(0., 1., 0.)
+        } else {
+            Vec3::new(1., 0., 0.)
+        };
+        let v = w.cross(&helper).unit();
+        let u = v.cross(&w);
+
+        Self { u, v, w }
+    }
+
+    //(a, b, c) along (u, v, w) to world space
+    pub fn transform(&self, local: &Vec3) -> Vec3 {
+        local.x * &self.u + local.y * &self.v + local.z * &self.w
+    }
+
+    pub fn to_local(&self, direction: &Vec3) -> Vec3 {
+        Vec3::new(
+            direction.dot(&self.u),
+            direction.dot(&self.v),
+            direction.dot(&self.w),
+        )
+    }
+
+    //cosine of the angle between direction and w
+    fn cos_theta(&self, direction: &Vec3) -> f64 {
+        self.w.dot(&direction.unit())
+    }
+
+    //unit direction at polar angle acos(cos_theta) from w and a uniformly random azimuth
+    fn around_w(&self, cos_theta: f64) -> Vec3 {
+        let sin_theta = (1. - cos_theta * cos_theta).max(0.).sqrt();
+        let phi = 2. * PI * random_f64();
+        self.transform(&Vec3::new(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ))
+    }
+
+    //density proportional to the cosine to w, what a Lambertian surface reflects
+    pub fn random_cosine(&self) -> (Vec3, f64) {
+        let cos_theta = random_f64().sqrt();
+        (self.around_w(cos_theta), cos_theta / PI)
+    }
+
+    pub fn cosine_pdf(&self, direction: &Vec3) -> f64 {
+        self.cos_theta(direction).max(0.) / PI
+    }
+
+    //every direction on the w side equally likely
+    pub fn random_hemisphere(&self) -> (Vec3, f64) {
+        (self.around_w(random_f64()), 1. / (2. * PI))
+    }
+
+    pub fn hemisphere_pdf(&self, direction: &Vec3) -> f64 {
+        if self.cos_theta(direction) > 0. {
+            1. / (2. * PI)
+        } else {
+            0.
+        }
+    }
+
+    //uniform inside the cone of directions within acos(cos_theta_max) of w, e.g. the
+    //directions that reach a sphere
+    pub fn random_cone(&self, cos_theta_max: f64) -> (Vec3, f64) {
+        let cos_theta = 1. + random_f64() * (cos_theta_max - 1.);
+        (self.around_w(cos_theta), Self::cone_density(cos_theta_max))
+    }
+
+    pub fn cone_pdf(&self, direction: &Vec3, cos_theta_max: f64) -> f64 {
+        if self.cos_theta(direction) >= cos_theta_max {
+            Self::cone_density(cos_theta_max)
+        } else {
+            0.
+        }
+    }
+
+    fn cone_density(cos_theta_max: f64) -> f64 {
+        1. / (2. * PI * (1. - cos_theta_max))
+    }
+
+    //GGX (Trowbridge-Reitz) microfacet normal around w with roughness alpha, the density is
+    //D(h) cos(theta_h)
+    pub fn random_ggx(&self, alpha: f64) -> (Vec3, f64) {
+        let alpha = alpha.max(MIN_GGX_ALPHA);
+        let xi = random_f64();
+        let alpha_squared = alpha * alpha;
+        let cos_theta_squared = (1. - xi) / (xi * (alpha_squared - 1.) + 1.);
+        let half = self.around_w(cos_theta_squared.sqrt());
+        let pdf = self.ggx_pdf(&half, alpha);
+        (half, pdf)
+    }
+
+    pub fn ggx_pdf(&self, half: &Vec3, alpha: f64) -> f64 {
+        let cos_theta = self.cos_theta(half);
+        if cos_theta <= 0. {
+            return 0.;
+        }
+        let alpha = alpha.max(MIN_GGX_ALPHA);
+        let alpha_squared = alpha * alpha;
+        let denominator = (alpha_squared - 1.) * cos_theta * cos_theta + 1.;
+        alpha_squared / (PI * denominator * denominator) * cos_theta
+    }
+
+    //outgoing (unit, pointing away from the surface) reflected about a GGX microfacet normal,
+    //the density of the normal divided by 4 |o.h|. The result can point below the surface,
+    //where it carries no light
+    pub fn random_ggx_reflection(&self, outgoing: &Vec3, alpha: f64) -> (Vec3, f64) {
+        let (half, half_pdf) = self.random_ggx(alpha);
+        let o_dot_h = outgoing.dot(&half);
+        let direction = 2. * o_dot_h * &half - outgoing;
+        (direction, half_pdf / (4. * o_dot_h.abs()))
+    }
+
+    pub fn ggx_reflection_pdf(&self, outgoing: &Vec3, direction: &Vec3, alpha: f64) -> f64 {
+        let sum = outgoing + direction.unit();
+        if sum.near_zero() {
+            return 0.;
+        }
+        //the microfacet normal is the half vector on the w side
+        let half = if self.w.dot(&sum) < 0. { -sum } else { sum }.unit();
+        self.ggx_pdf(&half, alpha) / (4. * outgoing.dot(&half).abs())
+    }
+}
@@ -0,0 +1,157 @@
+use crate::hittable::*;
+use crate::onb::*;
+use crate::utility::*;
+use crate::vec3::*;
+
+//distribution of directions that can both be sampled and evaluated
+pub trait Pdf {
+    //solid angle density of generate returning direction
+    fn value(&self, direction: &Vec3) -> f64;
+
+    fn generate(&self) -> Vec3;
+}
+
+//cosine weighted hemisphere around normal
+pub struct CosinePdf {
+    uvw: Onb,
+}
+
+impl CosinePdf {
+    pub fn new(normal: &Vec3) -> Self {
+        Self {
+            uvw: Onb::new(normal),
+        }
+    }
+}
+
+impl Pdf for CosinePdf {
+    fn value(&self, direction: &Vec3) -> f64 {
+        self.uvw.cosine_pdf(direction)
+    }
+
+    fn generate(&self) -> Vec3 {
+        self.uvw.random_cosine().0
+    }
+}
+
+//every direction equally likely
+pub struct SpherePdf;
+
+impl Pdf for SpherePdf {
+    fn value(&self, direction: &Vec3) -> f64 {
+        1. / (4. * PI)
+    }
+
+    fn generate(&self) -> Vec3 {
+        Vec3::random_unit_vector()
+    }
+}
+
+//reflected direction moved to a random point of a sphere with radius fuzz around its tip,
+//how Metal blurs reflections
+pub struct FuzzPdf {
+    reflected: Vec3,
+    fuzz: f64,
+}
+
+impl FuzzPdf {
+    pub fn new(reflected: &Vec3, fuzz: f64) -> Self {
+        Self {
+            reflected: reflected.unit(),
+            fuzz,
+        }
+    }
+}
+
+impl Pdf for FuzzPdf {
+    fn value(&self, direction: &Vec3) -> f64 {
+        let cos_theta = self.reflected.dot(&direction.unit());
+        let discriminant = self.fuzz * self.fuzz - (1. - cos_theta * cos_theta);
+        //generate can round a direction grazing the sphere to just outside it, that still
+        //counts as grazing
+        let tolerance = 1e-12;
+        if discriminant <= -tolerance || cos_theta <= 0. {
+            return 0.;
+        }
+
+        //the direction crosses the sphere at up to two distances t, each one adding
+        //t^2 / (4 pi fuzz root) from the uniform density over the sphere's area
+        let root = discriminant.max(tolerance).sqrt();
+        let t_squared: f64 = [cos_theta - root, cos_theta + root]
+            .iter()
+            .filter(|t| **t > 0.)
+            .map(|t| t * t)
+            .sum();
+        t_squared / (4. * PI * self.fuzz * root)
+    }
+
+    fn generate(&self) -> Vec3 {
+        &self.reflected + self.fuzz * Vec3::random_unit_vector()
+    }
+}
+
+//directions from origin towards random points of objects, usually the scene's lights
+pub struct HittablePdf<'a> {
+    objects: &'a dyn Hittable,
+    origin: Point3,
+}
+
+impl<'a> HittablePdf<'a> {
+    pub fn new(objects: &'a dyn Hittable, origin: &Point3) -> Self {
+        Self {
+            objects,
+            origin: origin.clone(),
+        }
+    }
+}
+
+impl Pdf for HittablePdf<'_> {
+    fn value(&self, direction: &Vec3) -> f64 {
+        self.objects.pdf_value(&self.origin, direction)
+    }
+
+    fn generate(&self) -> Vec3 {
+        self.objects.random(&self.origin)
+    }
+}
+
+//samples a with probability weight and b otherwise
+pub struct MixturePdf<'a> {
+    a: &'a dyn Pdf,
+    b: &'a dyn Pdf,
+    weight: f64,
+}
+
+impl<'a> MixturePdf<'a> {
+    pub fn new(a: &'a dyn Pdf, b: &'a dyn Pdf) -> Self {
+        Self::new_with_weight(a, b, 0.5)
+    }
+
+    pub fn new_with_weight(a: &'a dyn Pdf, b: &'a dyn Pdf, weight: f64) -> Self {
+        Self { a, b, weight }
+    }
+}
+
+impl Pdf for MixturePdf<'_> {
+    fn value(&self, direction: &Vec3) -> f64 {
+        self.weight * self.a.value(direction) + (1. - self.weight) * self.b.value(direction)
+    }
+
+    fn generate(&self) -> Vec3 {
+        if random_f64() < self.weight {
+            self.a.generate()
+        } else {
+            self.b.generate()
+        }
+    }
+}
+
+//multiple importance sampling weight of a sample taken with density pdf, when another
+//strategy could have taken it with density other_pdf
+pub fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    if other_pdf == 0. {
+        return 1.;
+    }
+    let pdf_squared = pdf * pdf;
+    pdf_squared / (pdf_squared + other_pdf * other_pdf)
+}
@@ -0,0 +1,115 @@
+use crate::vec3::*;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+const POINT_COUNT: usize = 256;
+
+//gradient noise on the integer lattice, the same seed always gives the same field
+pub struct Perlin {
+    randvec: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        let randvec = (0..POINT_COUNT)
+            .map(|_| {
+                let v = Vec3::new(
+                    rng.gen_range(-1. ..1.),
+                    rng.gen_range(-1. ..1.),
+                    rng.gen_range(-1. ..1.),
+                );
+                if v.near_zero() {
+                    Vec3::new(1., 0., 0.)
+                } else {
+                    v.unit()
+                }
+            })
+            .collect();
+
+        Self {
+            randvec,
+            perm_x: Self::generate_perm(&mut rng),
+            perm_y: Self::generate_perm(&mut rng),
+            perm_z: Self::generate_perm(&mut rng),
+        }
+    }
+
+    //noise in [-1, 1], zero at every lattice point
+    pub fn noise(&self, p: &Point3) -> f64 {
+        let u = p.x - p.x.floor();
+        let v = p.y - p.y.floor();
+        let w = p.z - p.z.floor();
+
+        let i = p.x.floor() as i64;
+        let j = p.y.floor() as i64;
+        let k = p.z.floor() as i64;
+
+        let mut c: [[[Vec3; 2]; 2]; 2] = Default::default();
+        for (di, plane) in c.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, corner) in row.iter_mut().enumerate() {
+                    let index = self.perm_x[Self::wrap(i + di as i64)]
+                        ^ self.perm_y[Self::wrap(j + dj as i64)]
+                        ^ self.perm_z[Self::wrap(k + dk as i64)];
+                    *corner = self.randvec[index].clone();
+                }
+            }
+        }
+
+        Self::perlin_interp(&c, u, v, w)
+    }
+
+    //sum of octaves with halving weight and doubling frequency
+    pub fn turb(&self, p: &Point3, depth: usize) -> f64 {
+        let mut accum = 0.;
+        let mut temp_p = p.clone();
+        let mut weight = 1.;
+
+        for _ in 0..depth {
+            accum += weight * self.noise(&temp_p);
+            weight *= 0.5;
+            temp_p = 2. * temp_p;
+        }
+
+        accum.abs()
+    }
+
+    fn wrap(n: i64) -> usize {
+        (n & (POINT_COUNT as i64 - 1)) as usize
+    }
+
+    fn generate_perm(rng: &mut StdRng) -> Vec<usize> {
+        let mut perm: Vec<usize> = (0..POINT_COUNT).collect();
+        perm.shuffle(rng);
+        perm
+    }
+
+    //trilinear interpolation of the corner gradients with Hermite smoothing
+    fn perlin_interp(c: &[[[Vec3; 2]; 2]; 2], u: f64, v: f64, w: f64) -> f64 {
+        let uu = u * u * (3. - 2. * u);
+        let vv = v * v * (3. - 2. * v);
+        let ww = w * w * (3. - 2. * w);
+        let mut accum = 0.;
+
+        for (i, plane) in c.iter().enumerate() {
+            for (j, row) in plane.iter().enumerate() {
+                for (k, corner) in row.iter().enumerate() {
+                    let (fi, fj, fk) = (i as f64, j as f64, k as f64);
+                    let weight_v = Vec3::new(u - fi, v - fj, w - fk);
+                    accum += (fi * uu + (1. - fi) * (1. - uu))
+                        * (fj * vv + (1. - fj) * (1. - vv))
+                        * (fk * ww + (1. - fk) * (1. - ww))
+                        * corner.dot(&weight_v);
+                }
+            }
+        }
+
+        accum
+    }
+}
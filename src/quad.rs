@@ -0,0 +1,121 @@
+use crate::aabb::*;
+use crate::hittable::*;
+use crate::hittable_list::*;
+use crate::material::*;
+use crate::ray::*;
+use crate::utility::*;
+use crate::vec3::*;
+use crate::Interval;
+use std::sync::Arc;
+
+//parallelogram with corner q and edges u and v
+pub struct Quad {
+    pub q: Point3,
+    pub u: Vec3,
+    pub v: Vec3,
+    material: Arc<dyn Material>,
+    bbox: Aabb,
+    normal: Vec3,
+    d: f64,
+    w: Vec3, //maps a point in the plane to its (alpha, beta) edge coordinates
+    area: f64,
+}
+
+impl Quad {
+    pub fn new(q: Point3, u: Vec3, v: Vec3, material: Arc<dyn Material>) -> Self {
+        let n = u.cross(&v);
+        let normal = n.unit();
+        let d = normal.dot(&q);
+        let w = &n / n.dot(&n);
+        let area = n.length();
+
+        let bbox_diagonal1 = Aabb::new_from_points(&q, &(&q + &u + &v));
+        let bbox_diagonal2 = Aabb::new_from_points(&(&q + &u), &(&q + &v));
+        let bbox = Aabb::new_from_boxes(&bbox_diagonal1, &bbox_diagonal2).pad();
+
+        Self {
+            q,
+            u,
+            v,
+            material,
+            bbox,
+            normal,
+            d,
+            w,
+            area,
+        }
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let denom = self.normal.dot(&r.direction);
+
+        //no hit if the ray is parallel to the plane
+        if denom.abs() < 1e-8 {
+            return false;
+        }
+
+        let t = (self.d - self.normal.dot(&r.origin)) / denom;
+        if !ray_t.surrounds(t) {
+            return false;
+        }
+
+        let intersection = r.at(t);
+        let planar_hitpt_vector = &intersection - &self.q;
+        let alpha = self.w.dot(&planar_hitpt_vector.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&planar_hitpt_vector));
+
+        let unit = Interval::new_with_init(0., 1.);
+        if !unit.contains(alpha) || !unit.contains(beta) {
+            return false;
+        }
+
+        rec.t = t;
+        rec.p = intersection;
+        rec.u = alpha;
+        rec.v = beta;
+        rec.mat = self.material.clone();
+        rec.set_face_normal(r, &self.normal);
+
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        surface_pdf_value(self, self.area, origin, direction)
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        let p = &self.q + (random_f64() * &self.u) + (random_f64() * &self.v);
+        p - origin
+    }
+}
+
+//the six sides of the axis-aligned box with opposite corners a and b
+pub fn quad_box(a: &Point3, b: &Point3, material: Arc<dyn Material>) -> HittableList {
+    let mut sides = HittableList::new();
+
+    let min = Point3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+    let max = Point3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
+
+    let dx = Vec3::new(max.x - min.x, 0., 0.);
+    let dy = Vec3::new(0., max.y - min.y, 0.);
+    let dz = Vec3::new(0., 0., max.z - min.z);
+
+    let side = |q: Point3, u: &Vec3, v: &Vec3| -> Arc<dyn Hittable> {
+        Arc::new(Quad::new(q, u.clone(), v.clone(), material.clone()))
+    };
+
+    sides.add(side(Point3::new(min.x, min.y, max.z), &dx, &dy)); //front
+    sides.add(side(Point3::new(max.x, min.y, max.z), &-&dz, &dy)); //right
+    sides.add(side(Point3::new(max.x, min.y, min.z), &-&dx, &dy)); //back
+    sides.add(side(Point3::new(min.x, min.y, min.z), &dz, &dy)); //left
+    sides.add(side(Point3::new(min.x, max.y, max.z), &dx, &-&dz)); //top
+    sides.add(side(Point3::new(min.x, min.y, min.z), &dx, &dz)); //bottom
+
+    sides
+}
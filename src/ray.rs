@@ -2,18 +2,11 @@ use crate::image::*;
 use crate::vec3::*;
 use std::fmt::*;
 
+#[derive(Clone, Default)]
 pub struct Ray {
     pub origin: Point3,
     pub direction: Vec3,
-}
-
-impl Default for Ray {
-    fn default() -> Self {
-        Self {
-            origin: Default::default(),
-            direction: Default::default(),
-        }
-    }
+    pub time: f64,
 }
 
 impl Debug for Ray {
@@ -28,7 +21,15 @@ impl Debug for Ray {
 
 impl Ray {
     pub fn new(origin: Point3, direction: Vec3) -> Self {
-        Self { origin, direction }
+        Self::new_with_time(origin, direction, 0.)
+    }
+
+    pub fn new_with_time(origin: Point3, direction: Vec3, time: f64) -> Self {
+        Self {
+            origin,
+            direction,
+            time,
+        }
     }
 
     pub fn at(&self, scale: f64) -> Point3 {
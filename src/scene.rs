@@ -0,0 +1,411 @@
+use crate::camera::*;
+use crate::constant_medium::*;
+use crate::disk::*;
+use crate::hittable::*;
+use crate::hittable_list::*;
+use crate::material::*;
+use crate::obj::*;
+use crate::quad::*;
+use crate::sphere::*;
+use crate::texture::*;
+use crate::transform::*;
+use crate::triangle::*;
+use crate::utility::*;
+use crate::vec3::*;
+use crate::Color;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+//human-editable description of a whole scene, read from and written to .toml or .json
+//objects refer to materials by name, relative file paths are resolved against the scene file
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SceneDesc {
+    #[serde(default)]
+    pub camera: Camera,
+    #[serde(default)]
+    pub materials: BTreeMap<String, MaterialDesc>,
+    #[serde(default)]
+    pub objects: Vec<ObjectDesc>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MaterialDesc {
+    Lambertian { albedo: TextureDesc },
+    Metal { albedo: TextureDesc, fuzz: f64 },
+    Dielectric { ir: f64 },
+    DiffuseLight { emit: TextureDesc },
+    Isotropic { albedo: TextureDesc },
+}
+
+//either a plain color or a tagged texture table
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TextureDesc {
+    Solid(Color),
+    Pattern(PatternDesc),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PatternDesc {
+    Checker {
+        scale: f64,
+        even: Box<TextureDesc>,
+        odd: Box<TextureDesc>,
+    },
+    Image {
+        path: PathBuf,
+    },
+    Noise {
+        pattern: NoisePattern,
+        scale: f64,
+        #[serde(default)]
+        seed: u64,
+        #[serde(default = "black")]
+        dark: Color,
+        #[serde(default = "white")]
+        light: Color,
+    },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ObjectDesc {
+    #[serde(flatten)]
+    pub shape: ShapeDesc,
+    //applied in order, the first entry acts on the object first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transform: Vec<TransformDesc>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ShapeDesc {
+    Sphere {
+        center: Point3,
+        radius: f64,
+        material: String,
+        //end position at time 1 for moving spheres
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        center_end: Option<Point3>,
+    },
+    Quad {
+        q: Point3,
+        u: Vec3,
+        v: Vec3,
+        material: String,
+    },
+    Box {
+        a: Point3,
+        b: Point3,
+        material: String,
+    },
+    Triangle {
+        a: Point3,
+        b: Point3,
+        c: Point3,
+        material: String,
+    },
+    Disk {
+        center: Point3,
+        normal: Vec3,
+        radius: f64,
+        material: String,
+    },
+    //Wavefront .obj, materials come from its own .mtl files
+    Mesh {
+        path: PathBuf,
+        #[serde(default)]
+        smooth_shading: bool,
+    },
+    Medium {
+        boundary: Box<ObjectDesc>,
+        density: f64,
+        albedo: TextureDesc,
+    },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransformDesc {
+    Translate(Vec3),
+    Scale(Vec3),
+    RotateX(f64),
+    RotateY(f64),
+    RotateZ(f64),
+    Rotate { axis: Vec3, degrees: f64 },
+}
+
+fn black() -> Color {
+    Color::new(0., 0., 0.)
+}
+
+fn white() -> Color {
+    Color::new(1., 1., 1.)
+}
+
+impl SceneDesc {
+    //picks the format from the file extension
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        match extension(path).as_deref() {
+            Some("toml") => Self::from_toml(&fs::read_to_string(path)?),
+            Some("json") => Self::from_json(&fs::read_to_string(path)?),
+            _ => Err(unsupported_format("scene", path)),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let source = match extension(path).as_deref() {
+            Some("toml") => self.to_toml()?,
+            Some("json") => self.to_json()?,
+            _ => return Err(unsupported_format("scene", path)),
+        };
+        fs::write(path, source)
+    }
+
+    pub fn from_toml(source: &str) -> io::Result<Self> {
+        let scene: Self = toml::from_str(source).map_err(invalid_data)?;
+        scene.camera.check_shutter()?;
+        Ok(scene)
+    }
+
+    pub fn to_toml(&self) -> io::Result<String> {
+        toml::to_string(self).map_err(invalid_data)
+    }
+
+    pub fn from_json(source: &str) -> io::Result<Self> {
+        let scene: Self = serde_json::from_str(source).map_err(invalid_data)?;
+        scene.camera.check_shutter()?;
+        Ok(scene)
+    }
+
+    pub fn to_json(&self) -> io::Result<String> {
+        serde_json::to_string_pretty(self).map_err(invalid_data)
+    }
+
+    //builds every object, base_dir is where relative image and mesh paths start from
+    pub fn build_world(
+        &self,
+        base_dir: &Path,
+        warnings: &mut Vec<String>,
+    ) -> io::Result<HittableList> {
+        Ok(self.build(base_dir, warnings)?.0)
+    }
+
+    //the world and the list of lights in it, every diffuse_light object whose shape can be
+    //sampled (not meshes, media or moving spheres), problems that don't stop the scene from
+    //building are added to warnings for the front end to report
+    pub fn build(
+        &self,
+        base_dir: &Path,
+        warnings: &mut Vec<String>,
+    ) -> io::Result<(HittableList, HittableList)> {
+        let mut materials = BTreeMap::new();
+        for (name, desc) in &self.materials {
+            materials.insert(name.as_str(), desc.build(base_dir)?);
+        }
+
+        let mut world = HittableList::new();
+        let mut lights = HittableList::new();
+        for object in &self.objects {
+            let built = object.build(&materials, base_dir, warnings)?;
+            let is_light = object
+                .shape
+                .sampled_material()
+                .and_then(|name| self.materials.get(name))
+                .is_some_and(|material| matches!(material, MaterialDesc::DiffuseLight { .. }));
+            if is_light {
+                lights.add(built.clone());
+            }
+            world.add(built);
+        }
+        Ok((world, lights))
+    }
+}
+
+impl MaterialDesc {
+    pub fn build(&self, base_dir: &Path) -> io::Result<Arc<dyn Material>> {
+        Ok(match self {
+            MaterialDesc::Lambertian { albedo } => {
+                Arc::new(Lambertian::new_with_texture(albedo.build(base_dir)?))
+            }
+            MaterialDesc::Metal { albedo, fuzz } => {
+                Arc::new(Metal::new_with_texture(albedo.build(base_dir)?, *fuzz))
+            }
+            MaterialDesc::Dielectric { ir } => Arc::new(Dielectric::new(*ir)),
+            MaterialDesc::DiffuseLight { emit } => {
+                Arc::new(DiffuseLight::new_with_texture(emit.build(base_dir)?))
+            }
+            MaterialDesc::Isotropic { albedo } => {
+                Arc::new(Isotropic::new_with_texture(albedo.build(base_dir)?))
+            }
+        })
+    }
+}
+
+impl TextureDesc {
+    pub fn build(&self, base_dir: &Path) -> io::Result<Arc<dyn Texture>> {
+        Ok(match self {
+            TextureDesc::Solid(color) => Arc::new(SolidColor::new(color.clone())),
+            TextureDesc::Pattern(PatternDesc::Checker { scale, even, odd }) => Arc::new(
+                CheckerTexture::new(*scale, even.build(base_dir)?, odd.build(base_dir)?),
+            ),
+            TextureDesc::Pattern(PatternDesc::Image { path }) => {
+                Arc::new(ImageTexture::load(base_dir.join(path))?)
+            }
+            TextureDesc::Pattern(PatternDesc::Noise {
+                pattern,
+                scale,
+                seed,
+                dark,
+                light,
+            }) => Arc::new(NoiseTexture::new_with_colors(
+                *pattern,
+                *scale,
+                *seed,
+                dark.clone(),
+                light.clone(),
+            )),
+        })
+    }
+}
+
+impl ShapeDesc {
+    //material of the shapes that can be sampled as lights
+    fn sampled_material(&self) -> Option<&str> {
+        match self {
+            ShapeDesc::Sphere {
+                material,
+                center_end: None,
+                ..
+            }
+            | ShapeDesc::Quad { material, .. }
+            | ShapeDesc::Box { material, .. }
+            | ShapeDesc::Triangle { material, .. }
+            | ShapeDesc::Disk { material, .. } => Some(material),
+            _ => None,
+        }
+    }
+}
+
+impl ObjectDesc {
+    pub fn new(shape: ShapeDesc) -> Self {
+        Self {
+            shape,
+            transform: Vec::new(),
+        }
+    }
+
+    pub fn build(
+        &self,
+        materials: &BTreeMap<&str, Arc<dyn Material>>,
+        base_dir: &Path,
+        warnings: &mut Vec<String>,
+    ) -> io::Result<Arc<dyn Hittable>> {
+        let material = |name: &String| {
+            materials.get(name.as_str()).cloned().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unknown material: {}", name),
+                )
+            })
+        };
+
+        let object: Arc<dyn Hittable> = match &self.shape {
+            ShapeDesc::Sphere {
+                center,
+                radius,
+                material: name,
+                center_end,
+            } => match center_end {
+                Some(center_end) => Arc::new(Sphere::new_moving(
+                    center.clone(),
+                    center_end.clone(),
+                    *radius,
+                    material(name)?,
+                )),
+                None => Arc::new(Sphere::new(center.clone(), *radius, material(name)?)),
+            },
+            ShapeDesc::Quad {
+                q,
+                u,
+                v,
+                material: name,
+            } => Arc::new(Quad::new(q.clone(), u.clone(), v.clone(), material(name)?)),
+            ShapeDesc::Box {
+                a,
+                b,
+                material: name,
+            } => Arc::new(quad_box(a, b, material(name)?)),
+            ShapeDesc::Triangle {
+                a,
+                b,
+                c,
+                material: name,
+            } => Arc::new(Triangle::new(
+                a.clone(),
+                b.clone(),
+                c.clone(),
+                material(name)?,
+            )),
+            ShapeDesc::Disk {
+                center,
+                normal,
+                radius,
+                material: name,
+            } => Arc::new(Disk::new(
+                center.clone(),
+                normal.clone(),
+                *radius,
+                material(name)?,
+            )),
+            ShapeDesc::Mesh {
+                path,
+                smooth_shading,
+            } => Arc::new(load_obj(base_dir.join(path), *smooth_shading, warnings)?),
+            ShapeDesc::Medium {
+                boundary,
+                density,
+                albedo,
+            } => Arc::new(ConstantMedium::new_with_texture(
+                boundary.build(materials, base_dir, warnings)?,
+                *density,
+                albedo.build(base_dir)?,
+            )),
+        };
+
+        if self.transform.is_empty() {
+            return Ok(object);
+        }
+
+        let mut object_to_world = Mat4::identity();
+        for step in &self.transform {
+            let matrix = match step {
+                TransformDesc::Translate(offset) => Mat4::translation(offset),
+                TransformDesc::Scale(factors) => Mat4::scaling(factors),
+                TransformDesc::RotateX(degrees) => Mat4::rotation_x(*degrees),
+                TransformDesc::RotateY(degrees) => Mat4::rotation_y(*degrees),
+                TransformDesc::RotateZ(degrees) => Mat4::rotation_z(*degrees),
+                TransformDesc::Rotate { axis, degrees } => Mat4::rotation(axis, *degrees),
+            };
+            object_to_world = matrix * object_to_world;
+        }
+        match Transformed::new(object, object_to_world) {
+            Some(transformed) => Ok(Arc::new(transformed)),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "object transform is not invertible",
+            )),
+        }
+    }
+}
+
+fn invalid_data(error: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
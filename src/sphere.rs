@@ -1,33 +1,87 @@
+use crate::aabb::*;
 use crate::hittable::*;
 use crate::material::*;
+use crate::onb::*;
 use crate::ray::*;
+use crate::utility::*;
 use crate::vec3::*;
 use crate::Interval;
 use std::sync::Arc;
 pub struct Sphere {
-    pub center: Point3,
+    pub center: Point3, //center at time 0
     pub radius: f64,
     material: Arc<dyn Material>,
+    bbox: Aabb,
+    is_moving: bool,
+    center_vec: Vec3, //displacement of the center from time 0 to time 1
 }
-unsafe impl Sync for Sphere {}
 
 impl Sphere {
     pub fn new(center: Point3, radius: f64, material: Arc<dyn Material>) -> Self {
+        let bbox = Self::bbox_at(&center, radius);
         Self {
             center,
             radius,
             material,
+            bbox,
+            is_moving: false,
+            center_vec: Vec3::default(),
         }
     }
+
+    //sphere moving linearly from center1 at time 0 to center2 at time 1
+    pub fn new_moving(
+        center1: Point3,
+        center2: Point3,
+        radius: f64,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        let bbox = Aabb::new_from_boxes(
+            &Self::bbox_at(&center1, radius),
+            &Self::bbox_at(&center2, radius),
+        );
+        Self {
+            center_vec: &center2 - &center1,
+            center: center1,
+            radius,
+            material,
+            bbox,
+            is_moving: true,
+        }
+    }
+
+    pub fn center_at(&self, time: f64) -> Point3 {
+        if self.is_moving {
+            &self.center + time * &self.center_vec
+        } else {
+            self.center.clone()
+        }
+    }
+
+    //p is a point on the unit sphere, u is the angle around the Y axis from X=-1
+    //and v the angle from Y=-1 to Y=+1, both scaled to [0, 1]
+    pub fn get_sphere_uv(p: &Point3) -> (f64, f64) {
+        let theta = (-p.y).acos();
+        let phi = (-p.z).atan2(p.x) + PI;
+
+        (phi / (2. * PI), theta / PI)
+    }
+
+    fn bbox_at(center: &Point3, radius: f64) -> Aabb {
+        //negative radii (hollow spheres) still enclose the same volume
+        let rvec = Vec3::new(radius.abs(), radius.abs(), radius.abs());
+        Aabb::new_from_points(&(center - &rvec), &(center + &rvec))
+    }
 }
 // , material: Rc<Material>
 impl Hittable for Sphere {
     fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
-        let oc = &r.origin - &self.center;
+        let center = self.center_at(r.time);
+        let oc = &r.origin - &center;
 
         let a = r.direction.length_squared();
         let half_b = oc.dot(&r.direction);
-        let c = oc.length_squared() - &self.radius * &self.radius;
+        let c = oc.length_squared() - self.radius * self.radius;
 
         let discriminant = half_b * half_b - a * c;
         let sqrtd = discriminant.sqrt();
@@ -47,9 +101,44 @@ impl Hittable for Sphere {
         rec.t = root;
         rec.p = r.at(rec.t);
         rec.mat = self.material.clone();
-        let outward_normal = (&rec.p - &self.center) / self.radius;
-        rec.set_face_normal(&r, &outward_normal);
+        let outward_normal = (&rec.p - &center) / self.radius;
+        rec.set_face_normal(r, &outward_normal);
+        (rec.u, rec.v) = Self::get_sphere_uv(&((&rec.p - &center) / self.radius.abs()));
+
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    //moving spheres are sampled where they are at time 0
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let mut rec = HitRecord::new(self.material.clone());
+        let r = Ray::new(origin.clone(), direction.clone());
+        if !self.hit(&r, Interval::new_with_init(0.001, INFINITY), &mut rec) {
+            return 0.;
+        }
+
+        let radius_squared = self.radius * self.radius;
+        let distance_squared = (&self.center - origin).length_squared();
+        if distance_squared <= radius_squared {
+            return 1. / (4. * PI);
+        }
+        let cos_theta_max = (1. - radius_squared / distance_squared).sqrt();
+        1. / (2. * PI * (1. - cos_theta_max))
+    }
+
+    //uniform over the cone of directions the sphere covers, or over every direction from inside
+    fn random(&self, origin: &Point3) -> Vec3 {
+        let direction = &self.center - origin;
+        let radius_squared = self.radius * self.radius;
+        let distance_squared = direction.length_squared();
+        if distance_squared <= radius_squared {
+            return Vec3::random_unit_vector();
+        }
 
-        return true;
+        let cos_theta_max = (1. - radius_squared / distance_squared).sqrt();
+        Onb::new(&direction).random_cone(cos_theta_max).0
     }
 }
@@ -0,0 +1,179 @@
+use crate::image::*;
+use crate::interval::*;
+use crate::perlin::*;
+use crate::vec3::*;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color;
+}
+
+pub struct SolidColor {
+    albedo: Color,
+}
+
+impl SolidColor {
+    pub fn new(albedo: Color) -> Self {
+        Self { albedo }
+    }
+
+    pub fn new_with_rgb(red: f64, green: f64, blue: f64) -> Self {
+        Self::new(Color::new(red, green, blue))
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.albedo.clone()
+    }
+}
+
+//3D checker pattern, alternating between two textures every `scale` units in space
+pub struct CheckerTexture {
+    inv_scale: f64,
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+}
+
+impl CheckerTexture {
+    pub fn new(scale: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Self {
+        Self {
+            inv_scale: 1. / scale,
+            even,
+            odd,
+        }
+    }
+
+    pub fn new_with_colors(scale: f64, c1: Color, c2: Color) -> Self {
+        Self::new(
+            scale,
+            Arc::new(SolidColor::new(c1)),
+            Arc::new(SolidColor::new(c2)),
+        )
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let x = (self.inv_scale * p.x).floor() as i64;
+        let y = (self.inv_scale * p.y).floor() as i64;
+        let z = (self.inv_scale * p.z).floor() as i64;
+
+        if (x + y + z) % 2 == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+//albedo looked up from an image file, u runs left to right and v bottom to top
+pub struct ImageTexture {
+    image: Image,
+}
+
+impl ImageTexture {
+    pub fn new(image: Image) -> Self {
+        Self { image }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(Image::load(path)?))
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        //solid cyan makes missing texture data easy to spot
+        if self.image.height() == 0 {
+            return Color::new(0., 1., 1.);
+        }
+
+        let unit = Interval::new_with_init(0., 1.);
+        let u = unit.clamp(u);
+        let v = 1. - unit.clamp(v);
+
+        let col = ((u * self.image.width() as f64) as usize).min(self.image.width() - 1);
+        let row = ((v * self.image.height() as f64) as usize).min(self.image.height() - 1);
+
+        self.image.pixels[row][col].to_color()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoisePattern {
+    //smooth noise remapped to [0, 1]
+    Noise,
+    //several octaves of absolute noise
+    Turbulence,
+    //sine bands along z, distorted by turbulence
+    Marble,
+    //concentric rings around the y axis, distorted by turbulence
+    Wood,
+}
+
+//procedural texture blending between two colors by a Perlin noise pattern
+pub struct NoiseTexture {
+    noise: Perlin,
+    pattern: NoisePattern,
+    scale: f64,
+    dark: Color,
+    light: Color,
+}
+
+impl NoiseTexture {
+    pub fn new(pattern: NoisePattern, scale: f64, seed: u64) -> Self {
+        Self::new_with_colors(
+            pattern,
+            scale,
+            seed,
+            Color::new(0., 0., 0.),
+            Color::new(1., 1., 1.),
+        )
+    }
+
+    pub fn new_with_colors(
+        pattern: NoisePattern,
+        scale: f64,
+        seed: u64,
+        dark: Color,
+        light: Color,
+    ) -> Self {
+        Self {
+            noise: Perlin::new(seed),
+            pattern,
+            scale,
+            dark,
+            light,
+        }
+    }
+
+    //pattern value in [0, 1] at p
+    fn amount(&self, p: &Point3) -> f64 {
+        let turbulence_depth = 7;
+        match self.pattern {
+            NoisePattern::Noise => 0.5 * (1. + self.noise.noise(&(self.scale * p))),
+            NoisePattern::Turbulence => self.noise.turb(&(self.scale * p), turbulence_depth),
+            NoisePattern::Marble => {
+                let phase = self.scale * p.z + 10. * self.noise.turb(p, turbulence_depth);
+                0.5 * (1. + phase.sin())
+            }
+            NoisePattern::Wood => {
+                let radius = (p.x * p.x + p.z * p.z).sqrt();
+                let rings = self.scale * radius + 2. * self.noise.turb(p, turbulence_depth);
+                rings - rings.floor()
+            }
+        }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let t = Interval::new_with_init(0., 1.).clamp(self.amount(p));
+        (1. - t) * &self.dark + t * &self.light
+    }
+}
@@ -0,0 +1,150 @@
+use crate::hdr::*;
+use crate::image::*;
+use crate::interval::*;
+#[cfg(feature = "viewer")]
+use druid::Data;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "viewer", derive(Data))]
+#[serde(rename_all = "snake_case")]
+pub enum ToneMapOperator {
+    //no compression, everything above 1 clips
+    Clamp,
+    //x / (1 + x), never reaches white
+    Reinhard,
+    //Reinhard scaled so that white_point maps to 1
+    ExtendedReinhard,
+    //Narkowicz's fit of the ACES filmic curve
+    Aces,
+    //Hable's filmic curve from Uncharted 2
+    Uncharted2,
+}
+
+impl ToneMapOperator {
+    pub const ALL: [ToneMapOperator; 5] = [
+        ToneMapOperator::Clamp,
+        ToneMapOperator::Reinhard,
+        ToneMapOperator::ExtendedReinhard,
+        ToneMapOperator::Aces,
+        ToneMapOperator::Uncharted2,
+    ];
+
+    //same names as in scene files
+    pub fn name(&self) -> &'static str {
+        match self {
+            ToneMapOperator::Clamp => "clamp",
+            ToneMapOperator::Reinhard => "reinhard",
+            ToneMapOperator::ExtendedReinhard => "extended_reinhard",
+            ToneMapOperator::Aces => "aces",
+            ToneMapOperator::Uncharted2 => "uncharted2",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|operator| operator.name() == name)
+    }
+}
+
+//maps linear radiance to 8-bit sRGB: exposure, then the operator, then the sRGB transfer
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "viewer", derive(Data))]
+#[serde(default)]
+pub struct ToneMap {
+    pub operator: ToneMapOperator,
+    pub exposure: f64,    //in stops, each one doubles the brightness
+    pub white_point: f64, //smallest radiance mapped to white by extended Reinhard
+}
+
+impl Default for ToneMap {
+    fn default() -> Self {
+        Self {
+            operator: ToneMapOperator::Clamp,
+            exposure: 0.,
+            white_point: 4.,
+        }
+    }
+}
+
+impl ToneMap {
+    pub fn new(operator: ToneMapOperator) -> Self {
+        Self {
+            operator,
+            ..Default::default()
+        }
+    }
+
+    //display-linear value in [0, 1]
+    pub fn map(&self, radiance: f64) -> f64 {
+        let x = (radiance * self.exposure.exp2()).max(0.);
+        let mapped = match self.operator {
+            ToneMapOperator::Clamp => x,
+            ToneMapOperator::Reinhard => x / (1. + x),
+            ToneMapOperator::ExtendedReinhard => {
+                let white = self.white_point.max(1e-6);
+                x * (1. + x / (white * white)) / (1. + x)
+            }
+            ToneMapOperator::Aces => (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14),
+            ToneMapOperator::Uncharted2 => {
+                let exposure_bias = 2.;
+                let white = 11.2;
+                uncharted2_curve(exposure_bias * x) / uncharted2_curve(white)
+            }
+        };
+        Interval::new_with_init(0., 1.).clamp(mapped)
+    }
+
+    pub fn to_pixel(&self, color: &Color) -> Pixel {
+        let encode = |radiance: f64| {
+            let intensity = Interval::new_with_init(0., 0.999);
+            (256. * intensity.clamp(linear_to_srgb(self.map(radiance)))) as u8
+        };
+        Pixel {
+            r: encode(color.x),
+            g: encode(color.y),
+            b: encode(color.z),
+        }
+    }
+
+    pub fn apply(&self, image: &HdrImage) -> Image {
+        Image::new_with_init(image.height(), image.width(), |row, col| {
+            self.to_pixel(&image.get(row, col))
+        })
+    }
+
+    //float formats keep the raw radiance, everything else is tone mapped first
+    pub fn save(&self, image: &HdrImage, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        if HdrFormat::from_path(path).is_some() {
+            image.save(path)
+        } else {
+            self.apply(image).save(path)
+        }
+    }
+}
+
+fn uncharted2_curve(x: f64) -> f64 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f
+}
+
+//piecewise sRGB transfer function, linear near black
+pub fn linear_to_srgb(linear: f64) -> f64 {
+    if linear <= 0.0031308 {
+        12.92 * linear
+    } else {
+        1.055 * linear.powf(1. / 2.4) - 0.055
+    }
+}
+
+pub fn srgb_to_linear(encoded: f64) -> f64 {
+    if encoded <= 0.04045 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
+}
@@ -0,0 +1,311 @@
+use crate::aabb::*;
+use crate::hittable::*;
+use crate::ray::*;
+use crate::utility::*;
+use crate::vec3::*;
+use crate::Interval;
+use std::ops::Mul;
+use std::sync::Arc;
+
+//row-major 4x4 matrix acting on column vectors, points have w = 1 and directions w = 0
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mat4 {
+    pub m: [[f64; 4]; 4],
+}
+
+impl Default for Mat4 {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Mat4 {
+    pub fn new(m: [[f64; 4]; 4]) -> Self {
+        Self { m }
+    }
+
+    pub fn identity() -> Self {
+        Self::new([
+            [1., 0., 0., 0.],
+            [0., 1., 0., 0.],
+            [0., 0., 1., 0.],
+            [0., 0., 0., 1.],
+        ])
+    }
+
+    pub fn translation(offset: &Vec3) -> Self {
+        Self::new([
+            [1., 0., 0., offset.x],
+            [0., 1., 0., offset.y],
+            [0., 0., 1., offset.z],
+            [0., 0., 0., 1.],
+        ])
+    }
+
+    pub fn scaling(factors: &Vec3) -> Self {
+        Self::new([
+            [factors.x, 0., 0., 0.],
+            [0., factors.y, 0., 0.],
+            [0., 0., factors.z, 0.],
+            [0., 0., 0., 1.],
+        ])
+    }
+
+    //counter-clockwise rotation around axis when looking against it
+    pub fn rotation(axis: &Vec3, degrees: f64) -> Self {
+        let a = axis.unit();
+        let (sin, cos) = deg_to_rad(degrees).sin_cos();
+        let t = 1. - cos;
+
+        Self::new([
+            [
+                t * a.x * a.x + cos,
+                t * a.x * a.y - sin * a.z,
+                t * a.x * a.z + sin * a.y,
+                0.,
+            ],
+            [
+                t * a.x * a.y + sin * a.z,
+                t * a.y * a.y + cos,
+                t * a.y * a.z - sin * a.x,
+                0.,
+            ],
+            [
+                t * a.x * a.z - sin * a.y,
+                t * a.y * a.z + sin * a.x,
+                t * a.z * a.z + cos,
+                0.,
+            ],
+            [0., 0., 0., 1.],
+        ])
+    }
+
+    pub fn rotation_x(degrees: f64) -> Self {
+        Self::rotation(&Vec3::new(1., 0., 0.), degrees)
+    }
+
+    pub fn rotation_y(degrees: f64) -> Self {
+        Self::rotation(&Vec3::new(0., 1., 0.), degrees)
+    }
+
+    pub fn rotation_z(degrees: f64) -> Self {
+        Self::rotation(&Vec3::new(0., 0., 1.), degrees)
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut t = [[0.; 4]; 4];
+        for (i, row) in t.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.m[j][i];
+            }
+        }
+        Self::new(t)
+    }
+
+    //Gauss-Jordan elimination with partial pivoting, None for singular matrices
+    pub fn inverse(&self) -> Option<Self> {
+        let mut a = self.m;
+        let mut inv = Self::identity().m;
+
+        for col in 0..4 {
+            let pivot = (col..4).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let scale = 1. / a[col][col];
+            for j in 0..4 {
+                a[col][j] *= scale;
+                inv[col][j] *= scale;
+            }
+
+            for row in 0..4 {
+                if row != col {
+                    let factor = a[row][col];
+                    for j in 0..4 {
+                        a[row][j] -= factor * a[col][j];
+                        inv[row][j] -= factor * inv[col][j];
+                    }
+                }
+            }
+        }
+
+        Some(Self::new(inv))
+    }
+
+    pub fn transform_point(&self, p: &Point3) -> Point3 {
+        let m = &self.m;
+        let x = m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3];
+        let y = m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3];
+        let z = m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3];
+        let w = m[3][0] * p.x + m[3][1] * p.y + m[3][2] * p.z + m[3][3];
+
+        if w == 1. {
+            Point3::new(x, y, z)
+        } else {
+            Point3::new(x / w, y / w, z / w)
+        }
+    }
+
+    //determinant of the linear (upper-left 3x3) part
+    pub fn linear_determinant(&self) -> f64 {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    pub fn transform_vector(&self, v: &Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+
+    //applies rhs first, then self
+    fn mul(self, rhs: Self) -> Self::Output {
+        let mut m = [[0.; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[i][k] * rhs.m[k][j]).sum();
+            }
+        }
+        Mat4::new(m)
+    }
+}
+
+//places a shared object in the world through an affine transform, without copying its geometry
+pub struct Transformed {
+    object: Arc<dyn Hittable>,
+    object_to_world: Mat4,
+    world_to_object: Mat4,
+    normal_to_world: Mat4, //inverse transpose, keeps normals perpendicular under scaling
+    bbox: Aabb,
+}
+
+impl Transformed {
+    //None if the transform is not invertible
+    pub fn new(object: Arc<dyn Hittable>, object_to_world: Mat4) -> Option<Self> {
+        let world_to_object = object_to_world.inverse()?;
+        Some(Self::new_with_inverse(
+            object,
+            object_to_world,
+            world_to_object,
+        ))
+    }
+
+    fn new_with_inverse(
+        object: Arc<dyn Hittable>,
+        object_to_world: Mat4,
+        world_to_object: Mat4,
+    ) -> Self {
+        let bbox = Self::transform_box(&object.bounding_box(), &object_to_world);
+
+        Self {
+            object,
+            object_to_world,
+            world_to_object,
+            normal_to_world: world_to_object.transpose(),
+            bbox,
+        }
+    }
+
+    pub fn translate(object: Arc<dyn Hittable>, offset: &Vec3) -> Self {
+        Self::new_with_inverse(
+            object,
+            Mat4::translation(offset),
+            Mat4::translation(&-offset),
+        )
+    }
+
+    pub fn rotate_y(object: Arc<dyn Hittable>, degrees: f64) -> Self {
+        Self::new_with_inverse(
+            object,
+            Mat4::rotation_y(degrees),
+            Mat4::rotation_y(-degrees),
+        )
+    }
+
+    //None if a factor is zero
+    pub fn scale(object: Arc<dyn Hittable>, factors: &Vec3) -> Option<Self> {
+        Self::new(object, Mat4::scaling(factors))
+    }
+
+    //box around all eight transformed corners
+    fn transform_box(bbox: &Aabb, transform: &Mat4) -> Aabb {
+        if bbox.x.min > bbox.x.max || bbox.y.min > bbox.y.max || bbox.z.min > bbox.z.max {
+            return Aabb::default();
+        }
+
+        let mut result = Aabb::default();
+        for i in 0..8 {
+            let corner = Point3::new(
+                if i & 1 == 0 { bbox.x.min } else { bbox.x.max },
+                if i & 2 == 0 { bbox.y.min } else { bbox.y.max },
+                if i & 4 == 0 { bbox.z.min } else { bbox.z.max },
+            );
+            let p = transform.transform_point(&corner);
+            result = Aabb::new_from_boxes(&result, &Aabb::new_from_points(&p, &p));
+        }
+        result
+    }
+}
+
+impl Hittable for Transformed {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        //the direction is not normalized, so t means the same in both spaces
+        let object_r = Ray::new_with_time(
+            self.world_to_object.transform_point(&r.origin),
+            self.world_to_object.transform_vector(&r.direction),
+            r.time,
+        );
+
+        if !self.object.hit(&object_r, ray_t, rec) {
+            return false;
+        }
+
+        rec.p = self.object_to_world.transform_point(&rec.p);
+        //set_face_normal already oriented the normal against the ray, and the
+        //inverse transpose keeps that orientation
+        rec.normal = self.normal_to_world.transform_vector(&rec.normal).unit();
+
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let object_direction = self.world_to_object.transform_vector(direction);
+        let pdf = self.object.pdf_value(
+            &self.world_to_object.transform_point(origin),
+            &object_direction,
+        );
+        if pdf == 0. {
+            return 0.;
+        }
+
+        //the linear part M stretches solid angles around the object space direction s
+        //by |det M| / |M s|^3
+        let stretch = self
+            .object_to_world
+            .transform_vector(&object_direction.unit())
+            .length();
+        pdf * stretch.powi(3) / self.object_to_world.linear_determinant().abs()
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        let object_origin = self.world_to_object.transform_point(origin);
+        self.object_to_world
+            .transform_vector(&self.object.random(&object_origin))
+    }
+}
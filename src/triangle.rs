@@ -0,0 +1,103 @@
+use crate::aabb::*;
+use crate::hittable::*;
+use crate::material::*;
+use crate::ray::*;
+use crate::utility::*;
+use crate::vec3::*;
+use crate::Interval;
+use std::sync::Arc;
+
+//triangle with vertices a, b and c, the face normal follows the a -> b -> c winding
+pub struct Triangle {
+    pub a: Point3,
+    pub b: Point3,
+    pub c: Point3,
+    material: Arc<dyn Material>,
+    bbox: Aabb,
+    normal: Vec3,
+    d: f64,
+    w: Vec3, //maps a point in the plane to its (alpha, beta) barycentric coordinates
+    area: f64,
+}
+
+impl Triangle {
+    pub fn new(a: Point3, b: Point3, c: Point3, material: Arc<dyn Material>) -> Self {
+        let u = &b - &a;
+        let v = &c - &a;
+        let n = u.cross(&v);
+        let normal = n.unit();
+        let d = normal.dot(&a);
+        let w = &n / n.dot(&n);
+        let area = n.length() / 2.;
+
+        let bbox = Aabb::new_from_boxes(
+            &Aabb::new_from_points(&a, &b),
+            &Aabb::new_from_points(&c, &c),
+        )
+        .pad();
+
+        Self {
+            a,
+            b,
+            c,
+            material,
+            bbox,
+            normal,
+            d,
+            w,
+            area,
+        }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let denom = self.normal.dot(&r.direction);
+
+        //no hit if the ray is parallel to the plane
+        if denom.abs() < 1e-8 {
+            return false;
+        }
+
+        let t = (self.d - self.normal.dot(&r.origin)) / denom;
+        if !ray_t.surrounds(t) {
+            return false;
+        }
+
+        let intersection = r.at(t);
+        let planar_hitpt_vector = &intersection - &self.a;
+        let alpha = self.w.dot(&planar_hitpt_vector.cross(&(&self.c - &self.a)));
+        let beta = self.w.dot(&(&self.b - &self.a).cross(&planar_hitpt_vector));
+
+        if alpha < 0. || beta < 0. || alpha + beta > 1. {
+            return false;
+        }
+
+        rec.t = t;
+        rec.p = intersection;
+        rec.u = alpha;
+        rec.v = beta;
+        rec.mat = self.material.clone();
+        rec.set_face_normal(r, &self.normal);
+
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        surface_pdf_value(self, self.area, origin, direction)
+    }
+
+    //uniform over the area, folding the unit square onto the triangle
+    fn random(&self, origin: &Point3) -> Vec3 {
+        let (mut alpha, mut beta) = (random_f64(), random_f64());
+        if alpha + beta > 1. {
+            (alpha, beta) = (1. - alpha, 1. - beta);
+        }
+        let p = &self.a + alpha * (&self.b - &self.a) + beta * (&self.c - &self.a);
+        p - origin
+    }
+}
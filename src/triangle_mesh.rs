@@ -0,0 +1,174 @@
+use crate::aabb::*;
+use crate::bvh::*;
+use crate::hittable::*;
+use crate::hittable_list::*;
+use crate::material::*;
+use crate::ray::*;
+use crate::vec3::*;
+use crate::Interval;
+use std::sync::Arc;
+
+//vertex data shared by every face of a mesh, faces index into these
+#[derive(Default)]
+pub struct MeshBuffers {
+    pub positions: Vec<Point3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f64, f64)>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MeshFace {
+    pub vertices: [usize; 3],
+    pub normals: Option<[usize; 3]>,
+    pub uvs: Option<[usize; 3]>,
+}
+
+struct MeshData {
+    buffers: Arc<MeshBuffers>,
+    faces: Vec<MeshFace>,
+    material: Arc<dyn Material>,
+    smooth_shading: bool,
+}
+
+//one face of a mesh, only used as a primitive of the mesh's own BVH
+struct MeshTriangle {
+    mesh: Arc<MeshData>,
+    face: usize,
+}
+
+//indexed triangle mesh with its own SAH BVH over the faces
+pub struct TriangleMesh {
+    bvh: FlatBvh,
+    triangle_count: usize,
+}
+
+impl TriangleMesh {
+    //smooth_shading interpolates vertex normals where the faces have them
+    pub fn new(
+        buffers: Arc<MeshBuffers>,
+        faces: Vec<MeshFace>,
+        material: Arc<dyn Material>,
+        smooth_shading: bool,
+    ) -> Self {
+        let triangle_count = faces.len();
+        let mesh = Arc::new(MeshData {
+            buffers,
+            faces,
+            material,
+            smooth_shading,
+        });
+
+        let mut triangles = HittableList::new();
+        for face in 0..triangle_count {
+            triangles.add(Arc::new(MeshTriangle {
+                mesh: mesh.clone(),
+                face,
+            }));
+        }
+
+        Self {
+            bvh: FlatBvh::new(&triangles, SplitMethod::Sah),
+            triangle_count,
+        }
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.triangle_count
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        self.bvh.hit(r, ray_t, rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bvh.bounding_box()
+    }
+}
+
+impl MeshTriangle {
+    fn face(&self) -> &MeshFace {
+        &self.mesh.faces[self.face]
+    }
+
+    fn position(&self, corner: usize) -> &Point3 {
+        &self.mesh.buffers.positions[self.face().vertices[corner]]
+    }
+}
+
+impl Hittable for MeshTriangle {
+    //Moller-Trumbore intersection
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let p0 = self.position(0);
+        let edge1 = self.position(1) - p0;
+        let edge2 = self.position(2) - p0;
+
+        let pvec = r.direction.cross(&edge2);
+        let det = edge1.dot(&pvec);
+
+        //no hit if the ray is parallel to the triangle
+        if det.abs() < 1e-12 {
+            return false;
+        }
+        let inv_det = 1. / det;
+
+        let tvec = &r.origin - p0;
+        let b1 = tvec.dot(&pvec) * inv_det;
+        if !(0. ..=1.).contains(&b1) {
+            return false;
+        }
+
+        let qvec = tvec.cross(&edge1);
+        let b2 = r.direction.dot(&qvec) * inv_det;
+        if b2 < 0. || b1 + b2 > 1. {
+            return false;
+        }
+
+        let t = edge2.dot(&qvec) * inv_det;
+        if !ray_t.surrounds(t) {
+            return false;
+        }
+
+        let face = self.face();
+        let buffers = &self.mesh.buffers;
+        let b0 = 1. - b1 - b2;
+
+        rec.t = t;
+        rec.p = r.at(t);
+        (rec.u, rec.v) = match face.uvs {
+            Some([uv0, uv1, uv2]) => {
+                let (uv0, uv1, uv2) = (buffers.uvs[uv0], buffers.uvs[uv1], buffers.uvs[uv2]);
+                (
+                    b0 * uv0.0 + b1 * uv1.0 + b2 * uv2.0,
+                    b0 * uv0.1 + b1 * uv1.1 + b2 * uv2.1,
+                )
+            }
+            None => (b1, b2),
+        };
+        rec.mat = self.mesh.material.clone();
+
+        //the geometric normal decides which side was hit, the shading normal follows it
+        rec.set_face_normal(r, &edge1.cross(&edge2).unit());
+        if let (true, Some([n0, n1, n2])) = (self.mesh.smooth_shading, face.normals) {
+            let shading_normal =
+                (b0 * &buffers.normals[n0] + b1 * &buffers.normals[n1] + b2 * &buffers.normals[n2])
+                    .unit();
+            rec.normal = if rec.front_face {
+                shading_normal
+            } else {
+                -shading_normal
+            };
+        }
+
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::new_from_boxes(
+            &Aabb::new_from_points(self.position(0), self.position(1)),
+            &Aabb::new_from_points(self.position(2), self.position(2)),
+        )
+        .pad()
+    }
+}
@@ -1,20 +1,48 @@
 use crate::interval::*;
-use rand::{self, thread_rng, Rng};
-use std::f64::{consts::PI as f64_pi, MAX};
+use rand::rngs::StdRng;
+use rand::{self, Rng, SeedableRng};
+use std::cell::RefCell;
+use std::f64::consts::PI as f64_pi;
+use std::io;
+use std::path::Path;
 
-pub const INFINITY: f64 = MAX;
+pub const INFINITY: f64 = f64::MAX;
 pub const PI: f64 = f64_pi;
 
 pub fn deg_to_rad(deg: f64) -> f64 {
     deg * PI / 180.
 }
 
+thread_local! {
+    //every random number of the renderer comes from this, one generator per thread
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
 //random f64 between 0 and 1
 pub fn random_f64() -> f64 {
-    let mut rng = thread_rng();
-    rng.gen::<f64>()
+    RNG.with(|rng| rng.borrow_mut().gen::<f64>())
+}
+
+//restarts the calling thread's sequence, the same seed gives the same numbers
+pub fn seed_random(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
 }
 
 pub fn random_f64_in_range(min: f64, max: f64) -> f64 {
     min + random_f64() * (max - min)
 }
+
+//lowercase file extension, which picks the file format
+pub(crate) fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase())
+}
+
+//kind names what was expected, e.g. "image" or "scene"
+pub(crate) fn unsupported_format(kind: &str, path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("unsupported {} format: {}", kind, path.display()),
+    )
+}
@@ -1,10 +1,15 @@
+#[cfg(feature = "viewer")]
 use druid::Data;
 use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
 use std::{fmt::*, iter::Sum, ops::*};
 
 use crate::utility::*;
 
-#[derive(Clone, Data)]
+//written as a plain [x, y, z] array in scene files
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "viewer", derive(Data))]
+#[serde(from = "[f64; 3]", into = "[f64; 3]")]
 pub struct Vec3 {
     pub x: f64,
     pub y: f64,
@@ -13,13 +18,15 @@ pub struct Vec3 {
 
 pub type Point3 = Vec3;
 
-impl Default for Vec3 {
-    fn default() -> Self {
-        Self {
-            x: Default::default(),
-            y: Default::default(),
-            z: Default::default(),
-        }
+impl From<[f64; 3]> for Vec3 {
+    fn from([x, y, z]: [f64; 3]) -> Self {
+        Self::new(x, y, z)
+    }
+}
+
+impl From<Vec3> for [f64; 3] {
+    fn from(v: Vec3) -> Self {
+        [v.x, v.y, v.z]
     }
 }
 
@@ -46,7 +53,7 @@ impl Vec3 {
 
     pub fn near_zero(&self) -> bool {
         let s = 1e-8;
-        return (self.x.abs() < s) && (self.y.abs() < s) && (self.z.abs() < s);
+        (self.x.abs() < s) && (self.y.abs() < s) && (self.z.abs() < s)
     }
 
     pub fn dot(&self, rhs: &Vec3) -> f64 {
@@ -73,6 +80,19 @@ impl Vec3 {
             }
         }
     }
+    pub fn random_in_unit_disk() -> Self {
+        loop {
+            let p = Self::new(
+                random_f64_in_range(-1., 1.),
+                random_f64_in_range(-1., 1.),
+                0.,
+            );
+            if p.length_squared() < 1. {
+                return p;
+            }
+        }
+    }
+
     pub fn random_unit_vector() -> Self {
         Self::unit(&Self::random_in_unit_sphere())
     }
@@ -86,14 +106,14 @@ impl Vec3 {
     }
 
     pub fn reflect(v: &Vec3, n: &Vec3) -> Self {
-        return v - 2. * v.dot(n) * n;
+        v - 2. * v.dot(n) * n
     }
 
     pub fn refract(uv: &Vec3, n: &Vec3, etai_over_etat: f64) -> Self {
         let cos_theta = ((-uv).dot(n)).min(1.);
         let r_out_perp = etai_over_etat * (uv + cos_theta * n);
         let r_out_parallel = -(1. - r_out_perp.length_squared()).abs().sqrt() * n;
-        return r_out_perp + r_out_parallel;
+        r_out_perp + r_out_parallel
     }
 
     pub fn random() -> Self {